@@ -0,0 +1,149 @@
+use core::convert::TryFrom;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mqttrs::*;
+
+const PAYLOAD_SIZES: [usize; 4] = [0, 64, 1024, 65536];
+
+/// One packet of each variant, with a small payload where applicable.
+fn packets() -> Vec<(&'static str, Packet<'static>)> {
+    let pid = Pid::try_from(42).unwrap();
+    vec![
+        (
+            "connect",
+            Connect {
+                protocol: Protocol::MQTT311,
+                keep_alive: 60,
+                client_id: "bench-client",
+                clean_session: true,
+                last_will: Some(LastWill {
+                    topic: "clients/bench-client",
+                    message: b"offline",
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                }),
+                username: Some("user"),
+                password: Some(b"password"),
+            }
+            .into(),
+        ),
+        (
+            "connack",
+            Connack {
+                session_present: false,
+                code: ConnectReturnCode::Accepted,
+            }
+            .into(),
+        ),
+        ("puback", Packet::Puback(pid)),
+        ("pubrec", Packet::Pubrec(pid)),
+        ("pubrel", Packet::Pubrel(pid)),
+        ("pubcomp", Packet::Pubcomp(pid)),
+        (
+            "subscribe",
+            Subscribe::new(
+                pid,
+                vec![
+                    SubscribeTopic {
+                        topic_path: "sensors/+/temp".into(),
+                        qos: QoS::AtLeastOnce,
+                    },
+                    SubscribeTopic {
+                        topic_path: "commands/#".into(),
+                        qos: QoS::ExactlyOnce,
+                    },
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .into(),
+        ),
+        (
+            "suback",
+            Suback::new(
+                pid,
+                vec![
+                    SubscribeReturnCodes::Success(QoS::AtLeastOnce),
+                    SubscribeReturnCodes::Failure,
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .into(),
+        ),
+        (
+            "unsubscribe",
+            Unsubscribe::new(
+                pid,
+                vec!["sensors/+/temp".into(), "commands/#".into()]
+                    .into_iter()
+                    .collect(),
+            )
+            .into(),
+        ),
+        ("unsuback", Packet::Unsuback(pid)),
+        ("pingreq", Packet::Pingreq),
+        ("pingresp", Packet::Pingresp),
+        ("disconnect", Packet::Disconnect),
+    ]
+}
+
+fn publish(payload: &[u8]) -> Packet<'_> {
+    Publish {
+        dup: false,
+        qospid: QosPid::AtLeastOnce(Pid::new()),
+        retain: false,
+        topic_name: "sensors/bench/temp",
+        payload,
+    }
+    .into()
+}
+
+fn encode_benches(c: &mut Criterion) {
+    let mut buf = vec![0u8; 70000];
+
+    let mut group = c.benchmark_group("encode");
+    for (name, packet) in packets() {
+        group.bench_function(name, |b| {
+            b.iter(|| encode_slice(&packet, &mut buf).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("encode_publish");
+    for &size in &PAYLOAD_SIZES {
+        let payload = vec![42u8; size];
+        let packet = publish(&payload);
+        group.throughput(Throughput::Bytes(packet.encoded_len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &packet, |b, packet| {
+            b.iter(|| encode_slice(packet, &mut buf).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decode_benches(c: &mut Criterion) {
+    let mut buf = vec![0u8; 70000];
+
+    let mut group = c.benchmark_group("decode");
+    for (name, packet) in packets() {
+        let len = encode_slice(&packet, &mut buf).unwrap();
+        let encoded = &buf[..len];
+        group.bench_function(name, |b| b.iter(|| decode_slice(encoded).unwrap()));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("decode_publish");
+    for &size in &PAYLOAD_SIZES {
+        let payload = vec![42u8; size];
+        let len = encode_slice(&publish(&payload), &mut buf).unwrap();
+        let encoded = &buf[..len];
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), encoded, |b, encoded| {
+            b.iter(|| decode_slice(encoded).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode_benches, decode_benches);
+criterion_main!(benches);
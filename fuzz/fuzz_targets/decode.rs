@@ -0,0 +1,66 @@
+//! Decode arbitrary bytes. Run with `cargo +nightly fuzz run decode` from the repository root, and
+//! add `--no-default-features` to fuzz the no_std build.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mqttrs::*;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_slice(data);
+
+    let mut iter = PacketIter::new(data);
+    for _ in &mut iter {}
+    assert!(iter.consumed() + iter.remaining().len() == data.len());
+
+    let mut out = [0u8; 64];
+    let _ = clone_packet(data, &mut out);
+
+    let lenient = DecodeOptions::new().lenient(true);
+    let mut warnings = Warnings::new();
+    let _ = decode_slice_with_warnings(data, &lenient, &mut warnings);
+
+    // Feed the decoders in chunks whose size is picked by the first byte.
+    let chunk = data.first().map_or(1, |b| usize::from(b % 16) + 1);
+    let mut decoder = Decoder::new(heapless::Vec::<u8, 64>::new());
+    let opts = DecodeOptions::new().lenient(true).max_packet_size(256);
+    #[cfg(feature = "std")]
+    let mut vec_decoder = Decoder::with_options(std::vec::Vec::new(), opts);
+    #[cfg(not(feature = "std"))]
+    let mut vec_decoder = Decoder::with_options(heapless::Vec::<u8, 256>::new(), opts);
+    for bytes in data.chunks(chunk) {
+        if decoder.feed(bytes).is_ok() {
+            while let Ok(Some(_)) = decoder.decode() {}
+        }
+        if vec_decoder.feed(bytes).is_ok() {
+            loop {
+                let mut warnings = Warnings::new();
+                match vec_decoder.decode_with_warnings(&mut warnings) {
+                    Ok(Some(_)) => (),
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    {
+        let opts = DecodeOptions::new().max_packet_size(256);
+        for opts in &[opts, opts.lenient(true)] {
+            let mut buf = bytes::BytesMut::from(data);
+            while let Ok(Some(packet)) = decode_bytes_with_options(&mut buf, opts) {
+                let _ = packet.as_borrowed();
+            }
+        }
+    }
+
+    for opts in &[DecodeOptions::new(), lenient] {
+        if let Ok(Some((subscribe, _))) = SubscribeRef::from_slice(data, opts) {
+            for _ in subscribe.topics() {}
+        }
+        if let Ok(Some((unsubscribe, _))) = UnsubscribeRef::from_slice(data, opts) {
+            for _ in unsubscribe.topics() {}
+        }
+        if let Ok(Some((suback, _))) = SubackRef::from_slice(data, opts) {
+            for _ in suback.return_codes() {}
+        }
+    }
+});
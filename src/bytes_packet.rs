@@ -0,0 +1,209 @@
+use crate::*;
+use bytes::{Bytes, BytesMut};
+
+/// Decode the next complete packet from a `BytesMut`, sharing its memory instead of copying it.
+///
+/// When a complete packet is available, its bytes are split off the front of `buf`, even if it
+/// then fails to decode. The topic and payload of a decoded [`Publish`] are reference-counted
+/// `Bytes` slices of those bytes, so forwarding a message to many subscribers doesn't copy it.
+/// Returns `Ok(None)`, leaving `buf` untouched, if more bytes are needed. Only available with the
+/// `std` feature.
+///
+/// ```
+/// # use mqttrs::*;
+/// # use bytes::BytesMut;
+/// let mut buf = BytesMut::from(&[0b00110000, 7, 0, 1, b'a', b'h', b'e', b'l', b'o', 0b11000000][..]);
+/// let payload = match decode_bytes(&mut buf) {
+///     Ok(Some(BytesPacket::Publish(p))) => {
+///         assert_eq!(p.topic_name(), "a");
+///         p.payload
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// };
+/// assert_eq!(&payload[..], b"helo");
+///
+/// // The buffer is left with the start of the next packet.
+/// assert_eq!(Ok(None), decode_bytes(&mut buf));
+/// assert_eq!(&buf[..], &[0b11000000]);
+/// ```
+///
+/// [`Publish`]: struct.Publish.html
+pub fn decode_bytes(buf: &mut BytesMut) -> Result<Option<BytesPacket>, Error> {
+    decode_bytes_with_options(buf, &DecodeOptions::new())
+}
+
+/// Same as [`decode_bytes()`], with size limits and lenient mode set by `opts`.
+///
+/// A packet bigger than the size limit is rejected with `Error::PacketTooLarge` as soon as its
+/// fixed header is received, leaving `buf` untouched. The connection should then be closed.
+///
+/// ```
+/// # use mqttrs::*;
+/// # use bytes::BytesMut;
+/// let opts = DecodeOptions::new().max_packet_size(1024);
+/// // Publish with a remaining length of 2048, body not received yet.
+/// let mut buf = BytesMut::from(&[0b00110000, 0x80, 0x10][..]);
+/// assert_eq!(Err(Error::PacketTooLarge), decode_bytes_with_options(&mut buf, &opts));
+/// ```
+///
+/// [`decode_bytes()`]: fn.decode_bytes.html
+pub fn decode_bytes_with_options(
+    buf: &mut BytesMut,
+    opts: &DecodeOptions,
+) -> Result<Option<BytesPacket>, Error> {
+    let len = match decoder::parse_header(buf, opts.lenient)? {
+        Some((fixed, _)) => {
+            opts.check_size(fixed.typ, fixed.packet_len())?;
+            fixed.packet_len()
+        }
+        None => return Ok(None),
+    };
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let frame = buf.split_to(len).freeze();
+    Ok(
+        decode_slice_with_options(&frame, opts)?
+            .map(|(packet, _)| BytesPacket::new(&frame, packet)),
+    )
+}
+
+/// [`Publish`] packet whose topic and payload share the memory of the decoded buffer.
+///
+/// Cloning it only increments reference counts.
+///
+/// [`Publish`]: struct.Publish.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct BytesPublish {
+    pub dup: bool,
+    pub qospid: QosPid,
+    pub retain: bool,
+    /// Always valid UTF-8, checked by `new()` or by the decoder.
+    topic_name: Bytes,
+    pub payload: Bytes,
+}
+
+impl BytesPublish {
+    /// Create a `BytesPublish`, failing if `topic_name` isn't a valid MQTT string.
+    pub fn new(
+        dup: bool,
+        qospid: QosPid,
+        retain: bool,
+        topic_name: Bytes,
+        payload: Bytes,
+    ) -> Result<Self, Error> {
+        let topic = core::str::from_utf8(&topic_name)
+            .map_err(|e| Error::InvalidString(Field::TopicName, e))?;
+        utils::check_str(topic, Field::TopicName)?;
+        Ok(BytesPublish {
+            dup,
+            qospid,
+            retain,
+            topic_name,
+            payload,
+        })
+    }
+
+    pub fn topic_name(&self) -> &str {
+        core::str::from_utf8(&self.topic_name).expect("topic_name was checked on creation")
+    }
+
+    /// The topic name as a `Bytes` handle, to share it without copying.
+    pub fn topic_name_bytes(&self) -> &Bytes {
+        &self.topic_name
+    }
+
+    /// Borrow the fields as a [`Publish`], for example to encode it.
+    ///
+    /// [`Publish`]: struct.Publish.html
+    pub fn as_borrowed(&self) -> Publish<'_> {
+        Publish {
+            dup: self.dup,
+            qospid: self.qospid,
+            retain: self.retain,
+            topic_name: self.topic_name(),
+            payload: &self.payload,
+        }
+    }
+}
+
+/// Packet returned by [`decode_bytes()`].
+///
+/// Publish packets are zero-copy. Connect packets, which are rare and small, are copied into an
+/// [`OwnedConnect`].
+///
+/// [`decode_bytes()`]: fn.decode_bytes.html
+/// [`OwnedConnect`]: struct.OwnedConnect.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytesPacket {
+    Connect(OwnedConnect),
+    Connack(Connack),
+    Publish(BytesPublish),
+    Puback(Pid),
+    Pubrec(Pid),
+    Pubrel(Pid),
+    Pubcomp(Pid),
+    Subscribe(Subscribe),
+    Suback(Suback),
+    Unsubscribe(Unsubscribe),
+    Unsuback(Pid),
+    Pingreq,
+    Pingresp,
+    Disconnect,
+}
+
+impl BytesPacket {
+    /// Convert a `packet` decoded from `frame`.
+    fn new(frame: &Bytes, packet: Packet) -> Self {
+        match packet {
+            Packet::Connect(connect) => BytesPacket::Connect(connect.into_owned()),
+            Packet::Connack(connack) => BytesPacket::Connack(connack),
+            Packet::Publish(publish) => BytesPacket::Publish(BytesPublish {
+                dup: publish.dup,
+                qospid: publish.qospid,
+                retain: publish.retain,
+                topic_name: frame.slice_ref(publish.topic_name.as_bytes()),
+                payload: frame.slice_ref(publish.payload),
+            }),
+            Packet::Puback(pid) => BytesPacket::Puback(pid),
+            Packet::Pubrec(pid) => BytesPacket::Pubrec(pid),
+            Packet::Pubrel(pid) => BytesPacket::Pubrel(pid),
+            Packet::Pubcomp(pid) => BytesPacket::Pubcomp(pid),
+            Packet::Subscribe(subscribe) => BytesPacket::Subscribe(subscribe),
+            Packet::Suback(suback) => BytesPacket::Suback(suback),
+            Packet::Unsubscribe(unsub) => BytesPacket::Unsubscribe(unsub),
+            Packet::Unsuback(pid) => BytesPacket::Unsuback(pid),
+            Packet::Pingreq => BytesPacket::Pingreq,
+            Packet::Pingresp => BytesPacket::Pingresp,
+            Packet::Disconnect => BytesPacket::Disconnect,
+        }
+    }
+
+    /// Borrow the fields as a [`Packet`], for example to encode it.
+    ///
+    /// Not everything is borrowed: [`Packet`] holds [`Subscribe`], [`Suback`] and [`Unsubscribe`]
+    /// by value, so those variants are cloned, copying their topic lists or return codes.
+    ///
+    /// [`Packet`]: enum.Packet.html
+    /// [`Subscribe`]: struct.Subscribe.html
+    /// [`Suback`]: struct.Suback.html
+    /// [`Unsubscribe`]: struct.Unsubscribe.html
+    pub fn as_borrowed(&self) -> Packet<'_> {
+        match self {
+            BytesPacket::Connect(connect) => Packet::Connect(connect.as_borrowed()),
+            BytesPacket::Connack(connack) => Packet::Connack(*connack),
+            BytesPacket::Publish(publish) => Packet::Publish(publish.as_borrowed()),
+            BytesPacket::Puback(pid) => Packet::Puback(*pid),
+            BytesPacket::Pubrec(pid) => Packet::Pubrec(*pid),
+            BytesPacket::Pubrel(pid) => Packet::Pubrel(*pid),
+            BytesPacket::Pubcomp(pid) => Packet::Pubcomp(*pid),
+            BytesPacket::Subscribe(subscribe) => Packet::Subscribe(subscribe.clone()),
+            BytesPacket::Suback(suback) => Packet::Suback(suback.clone()),
+            BytesPacket::Unsubscribe(unsub) => Packet::Unsubscribe(unsub.clone()),
+            BytesPacket::Unsuback(pid) => Packet::Unsuback(*pid),
+            BytesPacket::Pingreq => Packet::Pingreq,
+            BytesPacket::Pingresp => Packet::Pingresp,
+            BytesPacket::Disconnect => Packet::Disconnect,
+        }
+    }
+}
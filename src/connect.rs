@@ -23,36 +23,57 @@ impl Protocol {
         match (name, level) {
             ("MQIsdp", 3) => Ok(Protocol::MQIsdp),
             ("MQTT", 4) => Ok(Protocol::MQTT311),
+            #[cfg(feature = "std")]
             _ => Err(Error::InvalidProtocol(name.into(), level)),
+            #[cfg(not(feature = "std"))]
+            _ => Err(Error::InvalidProtocol(truncate_str(name), level)),
         }
     }
-    pub(crate) fn from_buffer<'a>(buf: &'a [u8], offset: &mut usize) -> Result<Self, Error> {
-        let protocol_name = read_str(buf, offset)?;
-        let protocol_level = buf[*offset];
-        *offset += 1;
-
-        Protocol::new(protocol_name, protocol_level)
+    pub(crate) fn from_buffer<'a>(
+        buf: &'a [u8],
+        offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
+    ) -> Result<Self, Error> {
+        if !ctx.lenient() {
+            let protocol_name = read_str(buf, offset, Field::ProtocolName, ctx)?;
+            let protocol_level = read_u8(buf, offset)?;
+            return Protocol::new(protocol_name, protocol_level);
+        }
+        let name = read_bytes(buf, offset)?;
+        let level = read_u8(buf, offset)?;
+        match core::str::from_utf8(name).map(|name| Protocol::new(name, level)) {
+            Ok(Ok(protocol)) => Ok(protocol),
+            _ => {
+                ctx.warn(Warning::UnknownProtocol { name, level });
+                Ok(Protocol::MQTT311)
+            }
+        }
     }
-    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+    /// Encoded protocol name and level.
+    pub(crate) fn bytes(self) -> &'static [u8] {
         match self {
-            Protocol::MQTT311 => {
-                let slice = &[0u8, 4, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 4];
-                for &byte in slice {
-                    write_u8(buf, offset, byte)?;
-                }
-                Ok(slice.len())
-            }
-            Protocol::MQIsdp => {
-                let slice = &[
-                    0u8, 4, 'M' as u8, 'Q' as u8, 'i' as u8, 's' as u8, 'd' as u8, 'p' as u8, 4,
-                ];
-                for &byte in slice {
-                    write_u8(buf, offset, byte)?;
-                }
-                Ok(slice.len())
-            }
+            Protocol::MQTT311 => &[0u8, 4, b'M', b'Q', b'T', b'T', 4],
+            Protocol::MQIsdp => &[0u8, 6, b'M', b'Q', b'I', b's', b'd', b'p', 3],
+        }
+    }
+    pub(crate) fn to_buffer(self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        let slice = self.bytes();
+        write_slice(buf, offset, slice)?;
+        Ok(slice.len())
+    }
+}
+
+/// Copy as many whole chars of `s` as fit in `N` bytes, so that a long name sent by a peer can't
+/// make the conversion panic.
+#[cfg(not(feature = "std"))]
+pub(crate) fn truncate_str<const N: usize>(s: &str) -> heapless::String<N> {
+    let mut res = heapless::String::new();
+    for c in s.chars() {
+        if res.push(c).is_err() {
+            break;
         }
     }
+    res
 }
 
 /// Message that the server should publish when the client disconnects.
@@ -85,8 +106,8 @@ pub enum ConnectReturnCode {
     NotAuthorized,
 }
 impl ConnectReturnCode {
-    fn to_u8(&self) -> u8 {
-        match *self {
+    pub(crate) const fn to_u8(self) -> u8 {
+        match self {
             ConnectReturnCode::Accepted => 0,
             ConnectReturnCode::RefusedProtocolVersion => 1,
             ConnectReturnCode::RefusedIdentifierRejected => 2,
@@ -132,17 +153,66 @@ pub struct Connack {
 }
 
 impl<'a> Connect<'a> {
-    pub(crate) fn from_buffer(buf: &'a [u8], offset: &mut usize) -> Result<Self, Error> {
-        let protocol = Protocol::from_buffer(buf, offset)?;
+    /// Start building a `Connect` for `client_id`, checked by [`ConnectBuilder::build()`].
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// let connect = Connect::builder("sensor-1")
+    ///     .keep_alive(30)
+    ///     .will(LastWill {
+    ///         topic: "sensors/1/status",
+    ///         message: b"offline",
+    ///         qos: QoS::AtLeastOnce,
+    ///         retain: true,
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(connect.keep_alive, 30);
+    ///
+    /// let res = Connect::builder("sensor-1").password(b"secret").build();
+    /// assert_eq!(res, Err(Error::PasswordWithoutUsername));
+    /// ```
+    ///
+    /// [`ConnectBuilder::build()`]: struct.ConnectBuilder.html#method.build
+    pub fn builder(client_id: &'a str) -> ConnectBuilder<'a> {
+        ConnectBuilder {
+            connect: Connect {
+                protocol: Protocol::MQTT311,
+                keep_alive: 0,
+                client_id,
+                clean_session: true,
+                last_will: None,
+                username: None,
+                password: None,
+            },
+        }
+    }
 
-        let connect_flags = buf[*offset];
-        let keep_alive = ((buf[*offset + 1] as u16) << 8) | buf[*offset + 2] as u16;
-        *offset += 3;
+    pub(crate) fn from_buffer(
+        buf: &'a [u8],
+        offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
+    ) -> Result<Self, Error> {
+        let protocol = Protocol::from_buffer(buf, offset, ctx)?;
 
-        let client_id = read_str(buf, offset)?;
+        let mut connect_flags = read_u8(buf, offset)?;
+        if let Err(e) = check_connect_flags(connect_flags) {
+            if !ctx.lenient() {
+                return Err(e);
+            }
+            ctx.warn(Warning::InvalidConnectFlags {
+                flags: connect_flags,
+            });
+            if connect_flags & 0b100 == 0 {
+                connect_flags &= !0b00111000;
+            }
+        }
+        let keep_alive = read_u16(buf, offset)?;
+
+        let client_id = read_str(buf, offset, Field::ClientId, ctx)?;
 
         let last_will = if connect_flags & 0b100 != 0 {
-            let will_topic = read_str(buf, offset)?;
+            let will_topic = read_str(buf, offset, Field::WillTopic, ctx)?;
             let will_message = read_bytes(buf, offset)?;
             let will_qod = QoS::from_u8((connect_flags & 0b11000) >> 3)?;
             Some(LastWill {
@@ -156,7 +226,7 @@ impl<'a> Connect<'a> {
         };
 
         let username = if connect_flags & 0b10000000 != 0 {
-            Some(read_str(buf, offset)?)
+            Some(read_str(buf, offset, Field::Username, ctx)?)
         } else {
             None
         };
@@ -180,24 +250,37 @@ impl<'a> Connect<'a> {
         })
     }
 
-    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
-        let header: u8 = 0b00010000;
-        let mut length: usize = 6 + 1 + 1; // NOTE: protocol_name(6) + protocol_level(1) + flags(1);
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Length of the packet, without the fixed header.
+    pub(crate) fn remaining_len(&self) -> usize {
+        // protocol + flags(1) + keep alive(2) + client_id(2+len)
+        let mut length = self.protocol.bytes().len() + 1 + 2 + 2 + self.client_id.len();
+        if let Some(last_will) = &self.last_will {
+            length += 2 + last_will.topic.len() + 2 + last_will.message.len();
+        };
+        if let Some(username) = self.username {
+            length += 2 + username.len();
+        };
+        if let Some(password) = self.password {
+            length += 2 + password.len();
+        };
+        length
+    }
+
+    fn flags(&self) -> u8 {
         let mut connect_flags: u8 = 0b00000000;
         if self.clean_session {
             connect_flags |= 0b10;
         };
-        length += 2 + self.client_id.len();
-        length += 2; // keep alive
-        if let Some(username) = self.username {
+        if self.username.is_some() {
             connect_flags |= 0b10000000;
-            length += username.len();
-            length += 2;
         };
-        if let Some(password) = self.password {
+        if self.password.is_some() {
             connect_flags |= 0b01000000;
-            length += password.len();
-            length += 2;
         };
         if let Some(last_will) = &self.last_will {
             connect_flags |= 0b00000100;
@@ -205,62 +288,188 @@ impl<'a> Connect<'a> {
             if last_will.retain {
                 connect_flags |= 0b00100000;
             };
-            length += last_will.message.len();
-            length += last_will.topic.len();
-            length += 4;
         };
-        check_remaining(buf, offset, length + 1)?;
+        connect_flags
+    }
 
+    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        if self.password.is_some() && self.username.is_none() {
+            return Err(Error::PasswordWithoutUsername);
+        }
+        let header: u8 = 0b00010000;
+        let length = self.remaining_len();
+        let connect_flags = self.flags();
         // NOTE: putting data into buffer.
-        write_u8(buf, offset, header)?;
-
-        let write_len = write_length(buf, offset, length)? + 1;
+        let write_len = start_packet(buf, offset, header, length)?;
         self.protocol.to_buffer(buf, offset)?;
 
         write_u8(buf, offset, connect_flags)?;
         write_u16(buf, offset, self.keep_alive)?;
 
-        write_string(buf, offset, self.client_id)?;
+        write_string(buf, offset, self.client_id, Field::ClientId)?;
 
         if let Some(last_will) = &self.last_will {
-            write_string(buf, offset, last_will.topic)?;
-            write_bytes(buf, offset, &last_will.message)?;
+            write_string(buf, offset, last_will.topic, Field::WillTopic)?;
+            write_bytes(buf, offset, last_will.message, Field::WillMessage)?;
         };
 
         if let Some(username) = self.username {
-            write_string(buf, offset, username)?;
+            write_string(buf, offset, username, Field::Username)?;
         };
         if let Some(password) = self.password {
-            write_bytes(buf, offset, password)?;
+            write_bytes(buf, offset, password, Field::Password)?;
         };
         // NOTE: END
         Ok(write_len)
     }
+
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub(crate) fn to_sink(&self, sink: &mut impl Sink) -> Result<usize, Error> {
+        if self.password.is_some() && self.username.is_none() {
+            return Err(Error::PasswordWithoutUsername);
+        }
+        utils::check_str(self.client_id, Field::ClientId)?;
+        if let Some(last_will) = &self.last_will {
+            utils::check_str(last_will.topic, Field::WillTopic)?;
+            utils::check_bytes(last_will.message, Field::WillMessage)?;
+        }
+        if let Some(username) = self.username {
+            utils::check_str(username, Field::Username)?;
+        }
+        if let Some(password) = self.password {
+            utils::check_bytes(password, Field::Password)?;
+        }
+
+        let mut head = [0u8; 5 + 9 + 3];
+        let mut offset = 0;
+        let write_len = write_header(&mut head, &mut offset, 0b00010000, self.remaining_len())?;
+        self.protocol.to_buffer(&mut head, &mut offset)?;
+        write_u8(&mut head, &mut offset, self.flags())?;
+        write_u16(&mut head, &mut offset, self.keep_alive)?;
+        sink.write_all(&head[..offset])?;
+
+        sink_string(sink, self.client_id, Field::ClientId)?;
+        if let Some(last_will) = &self.last_will {
+            sink_string(sink, last_will.topic, Field::WillTopic)?;
+            sink_bytes(sink, last_will.message, Field::WillMessage)?;
+        };
+        if let Some(username) = self.username {
+            sink_string(sink, username, Field::Username)?;
+        };
+        if let Some(password) = self.password {
+            sink_bytes(sink, password, Field::Password)?;
+        };
+        Ok(write_len)
+    }
+}
+
+/// Check the [MQTT 3.1.2.3] rules about connect flags.
+///
+/// [MQTT 3.1.2.3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+fn check_connect_flags(flags: u8) -> Result<(), Error> {
+    if flags & 0b1 != 0 {
+        Err(Error::ReservedConnectFlag)
+    } else if flags & 0b100 == 0 && flags & 0b00111000 != 0 {
+        Err(Error::InvalidWillFlags)
+    } else if flags & 0b01000000 != 0 && flags & 0b10000000 == 0 {
+        Err(Error::PasswordWithoutUsername)
+    } else {
+        Ok(())
+    }
 }
 
 impl Connack {
-    pub(crate) fn from_buffer<'a>(buf: &'a [u8], offset: &mut usize) -> Result<Self, Error> {
-        let flags = buf[*offset];
-        let return_code = buf[*offset + 1];
-        *offset += 2;
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(2)
+    }
+
+    pub(crate) fn from_buffer(buf: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        let flags = read_u8(buf, offset)?;
+        let return_code = read_u8(buf, offset)?;
         Ok(Connack {
             session_present: (flags & 0b1 == 1),
             code: ConnectReturnCode::from_u8(return_code)?,
         })
     }
-    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+    pub(crate) fn to_buffer(self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
         check_remaining(buf, offset, 4)?;
-        let header: u8 = 0b00100000;
-        let length: u8 = 2;
-        let mut flags: u8 = 0b00000000;
-        if self.session_present {
-            flags |= 0b1;
-        };
-        let rc = self.code.to_u8();
-        write_u8(buf, offset, header)?;
-        write_u8(buf, offset, length)?;
-        write_u8(buf, offset, flags)?;
-        write_u8(buf, offset, rc)?;
+        write_slice(
+            buf,
+            offset,
+            &encode_connack(self.session_present, self.code),
+        )?;
         Ok(4)
     }
 }
+
+/// Builder for a [`Connect`] packet, created by [`Connect::builder()`].
+///
+/// Defaults to `Protocol::MQTT311`, no keep alive, a clean session, and no will or credentials.
+///
+/// [`Connect`]: struct.Connect.html
+/// [`Connect::builder()`]: struct.Connect.html#method.builder
+#[derive(Debug, Clone)]
+pub struct ConnectBuilder<'a> {
+    connect: Connect<'a>,
+}
+
+impl<'a> ConnectBuilder<'a> {
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.connect.protocol = protocol;
+        self
+    }
+
+    /// Keep alive interval in seconds, `0` to disable it.
+    pub fn keep_alive(mut self, keep_alive: u16) -> Self {
+        self.connect.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn clean_session(mut self, clean_session: bool) -> Self {
+        self.connect.clean_session = clean_session;
+        self
+    }
+
+    pub fn will(mut self, last_will: LastWill<'a>) -> Self {
+        self.connect.last_will = Some(last_will);
+        self
+    }
+
+    pub fn username(mut self, username: &'a str) -> Self {
+        self.connect.username = Some(username);
+        self
+    }
+
+    pub fn password(mut self, password: &'a [u8]) -> Self {
+        self.connect.password = Some(password);
+        self
+    }
+
+    /// Check the fields and return the `Connect`.
+    ///
+    /// Fails if a field is too long or contains a disallowed code point, if the client id is empty
+    /// without a clean session, if the will topic is empty or contains wildcards, or if a password
+    /// is set without a username.
+    pub fn build(self) -> Result<Connect<'a>, Error> {
+        let connect = self.connect;
+        utils::check_str(connect.client_id, Field::ClientId)?;
+        if connect.client_id.is_empty() && !connect.clean_session {
+            return Err(Error::InvalidClientId);
+        }
+        if let Some(will) = &connect.last_will {
+            utils::check_topic_name(will.topic, Field::WillTopic)?;
+            utils::check_bytes(will.message, Field::WillMessage)?;
+        }
+        if let Some(username) = connect.username {
+            utils::check_str(username, Field::Username)?;
+        }
+        if let Some(password) = connect.password {
+            if connect.username.is_none() {
+                return Err(Error::PasswordWithoutUsername);
+            }
+            utils::check_bytes(password, Field::Password)?;
+        }
+        Ok(connect)
+    }
+}
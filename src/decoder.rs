@@ -1,4 +1,4 @@
-use crate::*;
+use crate::{subscribe::LimitedVec, utils::check_str, *};
 
 pub fn clone_packet(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
     if input.is_empty() {
@@ -17,7 +17,10 @@ pub fn clone_packet(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
     if let Some((_, remaining_len)) = read_header(input, &mut offset)? {
         let end = offset + remaining_len;
         let len = end - start;
-        output[..len].copy_from_slice(&input[start..end]);
+        output
+            .get_mut(..len)
+            .ok_or(Error::WriteZero)?
+            .copy_from_slice(&input[start..end]);
         Ok(len)
     } else {
         // Don't have a full packet
@@ -32,11 +35,10 @@ pub fn clone_packet(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
 ///
 /// ```
 /// # use mqttrs::*;
-/// # use bytes::*;
 /// // Fill a buffer with encoded data (probably from a `TcpStream`).
-/// let mut buf = BytesMut::from(&[0b00110000, 11,
-///                                0, 4, 't' as u8, 'e' as u8, 's' as u8, 't' as u8,
-///                                'h' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8] as &[u8]);
+/// let mut buf: &[u8] = &[0b00110000, 11,
+///                        0, 4, 't' as u8, 'e' as u8, 's' as u8, 't' as u8,
+///                        'h' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8];
 ///
 /// // Parse the bytes and check the result.
 /// match decode_slice(&mut buf) {
@@ -52,61 +54,691 @@ pub fn clone_packet(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
 /// [Packet]: ../enum.Packet.html
 /// [BytesMut]: https://docs.rs/bytes/1.0.0/bytes/struct.BytesMut.html
 pub fn decode_slice<'a>(buf: &'a [u8]) -> Result<Option<Packet<'a>>, Error> {
-    let mut offset = 0;
-    if let Some((header, remaining_len)) = read_header(buf, &mut offset)? {
-        let r = read_packet(header, remaining_len, buf, &mut offset)?;
-        Ok(Some(r))
+    Ok(decode_slice_with_len(buf)?.map(|(packet, _)| packet))
+}
+
+/// Decode bytes from a slice as a [Packet] enum, also returning the packet's total length.
+///
+/// The returned length covers the fixed header and the packet body, so `&buf[len..]` is where the
+/// next packet starts.
+///
+/// ```
+/// # use mqttrs::*;
+/// let buf = [0b11000000, 0, 0b11010000, 0];
+/// let (packet, len) = decode_slice_with_len(&buf).unwrap().unwrap();
+/// assert_eq!(packet, Packet::Pingreq);
+/// assert_eq!(&buf[len..], &[0b11010000, 0]);
+/// ```
+///
+/// [Packet]: ../enum.Packet.html
+pub fn decode_slice_with_len<'a>(buf: &'a [u8]) -> Result<Option<(Packet<'a>, usize)>, Error> {
+    decode_slice_with_options(buf, &DecodeOptions::new())
+}
+
+/// Same as [`decode_slice_with_len()`], using the given [`DecodeOptions`].
+///
+/// Size limits are checked as soon as the fixed header is available, so an oversized packet is
+/// rejected before its body is received.
+///
+/// ```
+/// # use mqttrs::*;
+/// let opts = DecodeOptions::new().max_packet_size(1024);
+/// // Publish packet with remaining_len=2048, body not received yet.
+/// let buf = [0b00110000, 0x80, 0x10];
+/// assert_eq!(Err(Error::PacketTooLarge), decode_slice_with_options(&buf, &opts));
+/// ```
+///
+/// [`decode_slice_with_len()`]: fn.decode_slice_with_len.html
+/// [`DecodeOptions`]: struct.DecodeOptions.html
+pub fn decode_slice_with_options<'a, const N: usize, const L: usize>(
+    buf: &'a [u8],
+    opts: &DecodeOptions<N, L>,
+) -> Result<Option<(Packet<'a, N, L>, usize)>, Error> {
+    decode_slice_with_warnings(buf, opts, &mut Warnings::new())
+}
+
+/// Same as [`decode_slice_with_options()`], appending the protocol violations tolerated by
+/// [lenient] decoding to `warnings`.
+///
+/// ```
+/// # use mqttrs::*;
+/// let opts = DecodeOptions::new().lenient(true);
+/// // Pingreq with reserved flags set.
+/// let buf = [0b11000101, 0];
+/// let mut warnings = Warnings::new();
+/// let res = decode_slice_with_warnings(&buf, &opts, &mut warnings);
+/// assert_eq!(Ok(Some((Packet::Pingreq, 2))), res);
+/// assert_eq!(warnings[0], Warning::ReservedFlags { typ: PacketType::Pingreq, flags: 0b0101 });
+/// ```
+///
+/// [`decode_slice_with_options()`]: fn.decode_slice_with_options.html
+/// [lenient]: struct.DecodeOptions.html#method.lenient
+pub fn decode_slice_with_warnings<'a, const N: usize, const L: usize>(
+    buf: &'a [u8],
+    opts: &DecodeOptions<N, L>,
+    warnings: &mut Warnings<'a>,
+) -> Result<Option<(Packet<'a, N, L>, usize)>, Error> {
+    if let Some((fixed, flags_ok)) = parse_header(buf, opts.lenient)? {
+        let len = fixed.packet_len();
+        opts.check_size(fixed.typ, len)?;
+        if buf.len() < len {
+            // Don't have a full packet
+            return Ok(None);
+        }
+        let mut ctx = DecodeCtx::new(opts, warnings);
+        if !flags_ok {
+            ctx.warn(Warning::ReservedFlags {
+                typ: fixed.typ,
+                flags: buf[0] & 0b1111,
+            });
+        }
+        let mut offset = fixed.header_len;
+        let r = read_packet(
+            fixed.header(),
+            fixed.remaining_len,
+            buf,
+            &mut offset,
+            &mut ctx,
+        )?;
+        Ok(Some((r, len)))
     } else {
-        // Don't have a full packet
         Ok(None)
     }
 }
 
-fn read_packet<'a>(
+/// Protocol violation tolerated by [lenient] decoding.
+///
+/// [lenient]: struct.DecodeOptions.html#method.lenient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning<'a> {
+    /// The fixed header flags don't have the value required for this packet type. The packet was
+    /// decoded as if they did.
+    ReservedFlags { typ: PacketType, flags: u8 },
+    /// The [`Connect`] packet has an unknown protocol name or level. It was decoded as
+    /// `Protocol::MQTT311`.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    UnknownProtocol { name: &'a [u8], level: u8 },
+    /// The [`Connect`] flags have the reserved bit set, will QoS or will retain without the will
+    /// flag, or a password without a username. The reserved bit and the will bits are ignored.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    InvalidConnectFlags { flags: u8 },
+    /// A string isn't valid UTF-8 or contains a disallowed code point, see
+    /// `Error::DisallowedCodePoint`. If it isn't valid UTF-8, the decoded field only contains the
+    /// valid prefix of `raw`.
+    InvalidString { field: Field, raw: &'a [u8] },
+}
+
+/// List of [`Warning`]s, filled by lenient decoding.
+///
+/// Without the `std` feature, this is a `heapless::Vec` and warnings that don't fit are dropped.
+///
+/// [`Warning`]: enum.Warning.html
+pub type Warnings<'a> = LimitedVec<Warning<'a>>;
+
+/// Decoding state passed to the per-packet decoders.
+pub(crate) struct DecodeCtx<'a, 'w> {
+    lenient: bool,
+    warnings: &'w mut Warnings<'a>,
+}
+
+impl<'a, 'w> DecodeCtx<'a, 'w> {
+    pub(crate) fn new<const N: usize, const L: usize>(
+        opts: &DecodeOptions<N, L>,
+        warnings: &'w mut Warnings<'a>,
+    ) -> Self {
+        DecodeCtx {
+            lenient: opts.lenient,
+            warnings,
+        }
+    }
+
+    pub(crate) fn lenient(&self) -> bool {
+        self.lenient
+    }
+
+    pub(crate) fn warn(&mut self, warning: Warning<'a>) {
+        #[cfg(feature = "std")]
+        self.warnings.push(warning);
+        #[cfg(not(feature = "std"))]
+        let _ = self.warnings.push(warning);
+    }
+}
+
+/// Decoding options.
+///
+/// Used by [`decode_slice_with_options()`], [`PacketIter`], [`Decoder`] and
+/// [`SubscribeRef::from_slice()`]. The default is to strictly follow the spec and to accept any
+/// packet up to the protocol's maximum size (256MB).
+///
+/// [`decode_slice_with_options()`]: fn.decode_slice_with_options.html
+/// [`PacketIter`]: struct.PacketIter.html
+/// [`Decoder`]: struct.Decoder.html
+/// [`SubscribeRef::from_slice()`]: struct.SubscribeRef.html#method.from_slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions<const N: usize = 5, const L: usize = 256> {
+    max_packet_size: Option<usize>,
+    max_type_size: [Option<usize>; 14],
+    pub(crate) lenient: bool,
+}
+
+impl DecodeOptions {
+    pub const fn new() -> Self {
+        DecodeOptions {
+            max_packet_size: None,
+            max_type_size: [None; 14],
+            lenient: false,
+        }
+    }
+}
+
+impl<const N: usize, const L: usize> DecodeOptions<N, L> {
+    /// Decode into packets with a different capacity.
+    ///
+    /// Without the `std` feature, decoded [`Subscribe`], [`Suback`] and [`Unsubscribe`] packets
+    /// hold at most `N2` topics or return codes, of at most `L2` bytes each. Bigger packets are
+    /// rejected with `Error::InvalidLength`. The default is 5 topics of 256 bytes. With `std`,
+    /// the capacities only change the type of the decoded packets.
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// let opts = DecodeOptions::new().capacity::<2, 16>();
+    /// let buf = [0b10010000, 4, 0, 10, 0, 1];
+    /// let res: Option<(Packet<2, 16>, usize)> = decode_slice_with_options(&buf, &opts).unwrap();
+    /// assert!(matches!(res, Some((Packet::Suback(_), 6))));
+    /// ```
+    ///
+    /// [`Subscribe`]: struct.Subscribe.html
+    /// [`Suback`]: struct.Suback.html
+    /// [`Unsubscribe`]: struct.Unsubscribe.html
+    pub const fn capacity<const N2: usize, const L2: usize>(self) -> DecodeOptions<N2, L2> {
+        DecodeOptions {
+            max_packet_size: self.max_packet_size,
+            max_type_size: self.max_type_size,
+            lenient: self.lenient,
+        }
+    }
+
+    /// Tolerate some protocol violations instead of returning an error.
+    ///
+    /// This is meant for diagnostic tools that need to read traffic from misbehaving peers, and
+    /// tolerates reserved fixed header flags, invalid connect flags, unknown protocol names and
+    /// levels, and invalid strings. Each violation is reported as a [`Warning`], use [`decode_slice_with_warnings()`]
+    /// or [`Decoder::decode_with_warnings()`] to get them.
+    ///
+    /// [`Warning`]: enum.Warning.html
+    /// [`decode_slice_with_warnings()`]: fn.decode_slice_with_warnings.html
+    /// [`Decoder::decode_with_warnings()`]: struct.Decoder.html#method.decode_with_warnings
+    pub const fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Reject packets bigger than `size` bytes, fixed header included, with
+    /// `Error::PacketTooLarge`.
+    pub const fn max_packet_size(mut self, size: usize) -> Self {
+        self.max_packet_size = Some(size);
+        self
+    }
+
+    /// Reject packets of type `typ` bigger than `size` bytes, fixed header included, with
+    /// `Error::PacketTooLarge`.
+    ///
+    /// This overrides `max_packet_size()` for that packet type, so it can be used to accept
+    /// bigger `Publish` packets for example.
+    pub const fn max_packet_size_for(mut self, typ: PacketType, size: usize) -> Self {
+        self.max_type_size[typ as usize] = Some(size);
+        self
+    }
+
+    pub(crate) fn check_size(&self, typ: PacketType, len: usize) -> Result<(), Error> {
+        match self.max_type_size[typ as usize].or(self.max_packet_size) {
+            Some(max) if len > max => Err(Error::PacketTooLarge),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<const N: usize, const L: usize> Default for DecodeOptions<N, L> {
+    fn default() -> Self {
+        DecodeOptions::new().capacity()
+    }
+}
+
+/// Iterator over all the complete packets in a slice.
+///
+/// Iteration stops at the first incomplete packet, or after yielding the first decoding error.
+/// Use [`remaining()`] to get the bytes that haven't been consumed yet, typically to keep them
+/// around until more data is received.
+///
+/// ```
+/// # use mqttrs::*;
+/// let buf = [0b11000000, 0, 0b11010000, 0, 0b11100000];
+/// let mut iter = PacketIter::new(&buf);
+/// assert_eq!(Some(Ok(Packet::Pingreq)), iter.next());
+/// assert_eq!(Some(Ok(Packet::Pingresp)), iter.next());
+/// assert_eq!(None, iter.next());
+/// assert_eq!(iter.remaining(), &[0b11100000]);
+/// ```
+///
+/// [`remaining()`]: struct.PacketIter.html#method.remaining
+#[derive(Debug, Clone)]
+pub struct PacketIter<'a, const N: usize = 5, const L: usize = 256> {
+    buf: &'a [u8],
+    opts: DecodeOptions<N, L>,
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> PacketIter<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self::with_options(buf, DecodeOptions::new())
+    }
+}
+
+impl<'a, const N: usize, const L: usize> PacketIter<'a, N, L> {
+    pub fn with_options(buf: &'a [u8], opts: DecodeOptions<N, L>) -> Self {
+        PacketIter {
+            buf,
+            opts,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Number of bytes consumed by the packets yielded so far.
+    pub fn consumed(&self) -> usize {
+        self.offset
+    }
+
+    /// The unconsumed tail of the buffer.
+    ///
+    /// After a decoding error, this starts with the packet that failed to decode.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.offset..]
+    }
+}
+
+impl<'a, const N: usize, const L: usize> Iterator for PacketIter<'a, N, L> {
+    type Item = Result<Packet<'a, N, L>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match decode_slice_with_options(self.remaining(), &self.opts) {
+            Ok(Some((packet, len))) => {
+                self.offset += len;
+                Some(Ok(packet))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a, const N: usize, const L: usize> core::iter::FusedIterator for PacketIter<'a, N, L> {}
+
+/// Storage for the bytes buffered by a [`Decoder`].
+///
+/// Implemented for `heapless::Vec<u8, N>`, and for `std::vec::Vec<u8>` when the `std` feature is
+/// enabled.
+///
+/// [`Decoder`]: struct.Decoder.html
+pub trait DecodeBuffer {
+    /// The buffered bytes.
+    fn as_slice(&self) -> &[u8];
+    /// Append `bytes` to the buffer, or return `Error::WriteZero` (leaving the buffer untouched)
+    /// if they don't fit.
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), Error>;
+    /// Remove the first `len` bytes of the buffer.
+    fn discard(&mut self, len: usize);
+    /// Maximum number of bytes that the buffer can hold, if limited.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+impl DecodeBuffer for std::vec::Vec<u8> {
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+    fn discard(&mut self, len: usize) {
+        self.drain(..len);
+    }
+}
+
+impl<const N: usize> DecodeBuffer for heapless::Vec<u8, N> {
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(bytes).map_err(|_| Error::WriteZero)
+    }
+    fn discard(&mut self, len: usize) {
+        let remaining = self.len() - len;
+        self.copy_within(len.., 0);
+        self.truncate(remaining);
+    }
+    fn capacity(&self) -> Option<usize> {
+        Some(N)
+    }
+}
+
+/// Stateful decoder, for input that arrives in arbitrary chunks.
+///
+/// Bytes passed to [`feed()`] are kept in an internal [`DecodeBuffer`] until [`decode()`] returns
+/// the packet they belong to. The packet borrows from the decoder, and its bytes are discarded on
+/// the next call.
+///
+/// ```
+/// # use mqttrs::*;
+/// // Use `std::vec::Vec<u8>` for a growable buffer.
+/// let mut decoder = Decoder::new(heapless::Vec::<u8, 64>::new());
+///
+/// decoder.feed(&[0b00110000, 11, 0, 4, b't', b'e']).unwrap();
+/// assert_eq!(Ok(None), decoder.decode());
+///
+/// decoder.feed(&[b's', b't', b'h', b'e', b'l', b'l', b'o', 0b11000000]).unwrap();
+/// match decoder.decode() {
+///     Ok(Some(Packet::Publish(p))) => assert_eq!(p.payload, b"hello"),
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// assert_eq!(Ok(None), decoder.decode());
+///
+/// decoder.feed(&[0]).unwrap();
+/// assert_eq!(Ok(Some(Packet::Pingreq)), decoder.decode());
+/// ```
+///
+/// Packets that can never fit in a fixed-capacity buffer, or that are bigger than the
+/// [`DecodeOptions`] limits, are rejected with `Error::PacketTooLarge` as soon as their fixed
+/// header is received.
+///
+/// After a decoding error, the packet that failed to decode is skipped if its length is known.
+/// This includes oversized packets: their bytes are dropped as they are fed, without being
+/// buffered. An invalid fixed header has no known length, so the same error is returned until the
+/// decoder is dropped. The MQTT spec requires closing the connection in all those cases anyway.
+///
+/// [`feed()`]: struct.Decoder.html#method.feed
+/// [`decode()`]: struct.Decoder.html#method.decode
+/// [`DecodeBuffer`]: trait.DecodeBuffer.html
+/// [`DecodeOptions`]: struct.DecodeOptions.html
+#[derive(Debug, Clone, Default)]
+pub struct Decoder<B, const N: usize = 5, const L: usize = 256> {
+    buf: B,
+    opts: DecodeOptions<N, L>,
+    /// Length of the last decoded packet, to discard at the next call.
+    consumed: usize,
+    /// The next packet's fixed header, once fully received, and whether its flags are valid.
+    header: Option<(FixedHeader, bool)>,
+    /// Bytes of a rejected oversized packet that haven't been received yet, to drop when fed.
+    skipping: usize,
+}
+
+impl<B: DecodeBuffer> Decoder<B> {
+    /// Create a decoder using `buf` as its internal buffer.
+    ///
+    /// The buffer should be empty: any existing content is decoded as if it had been fed.
+    pub fn new(buf: B) -> Self {
+        Self::with_options(buf, DecodeOptions::new())
+    }
+}
+
+impl<B: DecodeBuffer, const N: usize, const L: usize> Decoder<B, N, L> {
+    /// Create a decoder using `buf` as its internal buffer, and the given options.
+    pub fn with_options(buf: B, opts: DecodeOptions<N, L>) -> Self {
+        Decoder {
+            buf,
+            opts,
+            consumed: 0,
+            header: None,
+            skipping: 0,
+        }
+    }
+
+    /// Append bytes received from the network.
+    ///
+    /// Returns `Error::WriteZero` if the internal buffer is full. In that case, no bytes have
+    /// been buffered.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.discard_consumed();
+        let skip = self.skipping.min(bytes.len());
+        self.buf.extend(&bytes[skip..])?;
+        self.skipping -= skip;
+        Ok(())
+    }
+
+    /// Decode the next packet, or return `Ok(None)` if it hasn't been fully received yet.
+    pub fn decode(&mut self) -> Result<Option<Packet<'_, N, L>>, Error> {
+        self.decode_with_warnings(&mut Warnings::new())
+    }
+
+    /// Same as [`decode()`], appending the protocol violations tolerated by [lenient] decoding to
+    /// `warnings`.
+    ///
+    /// [`decode()`]: struct.Decoder.html#method.decode
+    /// [lenient]: struct.DecodeOptions.html#method.lenient
+    pub fn decode_with_warnings<'s>(
+        &'s mut self,
+        warnings: &mut Warnings<'s>,
+    ) -> Result<Option<Packet<'s, N, L>>, Error> {
+        self.discard_consumed();
+        let (fixed, flags_ok) = match self.header {
+            Some(h) => h,
+            None => match parse_header(self.buf.as_slice(), self.opts.lenient)? {
+                Some((fixed, flags_ok)) => {
+                    let len = fixed.packet_len();
+                    if self.opts.check_size(fixed.typ, len).is_err()
+                        || matches!(self.buf.capacity(), Some(cap) if len > cap)
+                    {
+                        self.skip(len);
+                        return Err(Error::PacketTooLarge);
+                    }
+                    self.header = Some((fixed, flags_ok));
+                    (fixed, flags_ok)
+                }
+                None => return Ok(None),
+            },
+        };
+        let len = fixed.packet_len();
+        if self.buf.as_slice().len() < len {
+            return Ok(None);
+        }
+        self.header = None;
+        self.consumed = len;
+        let buf = self.buf.as_slice();
+        let mut ctx = DecodeCtx::new(&self.opts, warnings);
+        if !flags_ok {
+            ctx.warn(Warning::ReservedFlags {
+                typ: fixed.typ,
+                flags: buf[0] & 0b1111,
+            });
+        }
+        let mut offset = fixed.header_len;
+        read_packet(
+            fixed.header(),
+            fixed.remaining_len,
+            buf,
+            &mut offset,
+            &mut ctx,
+        )
+        .map(Some)
+    }
+
+    /// Number of buffered bytes that haven't been decoded yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.as_slice().len() - self.consumed
+    }
+
+    /// Return the internal buffer, including the bytes of the last decoded packet.
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+
+    /// Drop the `len` bytes of a rejected packet, including those not received yet.
+    fn skip(&mut self, len: usize) {
+        let buffered = len.min(self.buf.as_slice().len());
+        self.buf.discard(buffered);
+        self.skipping = len - buffered;
+    }
+
+    fn discard_consumed(&mut self) {
+        if self.consumed > 0 {
+            self.buf.discard(self.consumed);
+            self.consumed = 0;
+        }
+    }
+}
+
+fn read_packet<'a, const N: usize, const L: usize>(
     header: Header,
     remaining_len: usize,
     buf: &'a [u8],
     offset: &mut usize,
-) -> Result<Packet<'a>, Error> {
-    Ok(match header.typ {
+    ctx: &mut DecodeCtx<'a, '_>,
+) -> Result<Packet<'a, N, L>, Error> {
+    // Confine the per-type decoders to this packet, so that a corrupt inner length can't make
+    // them read into the next packet. `read_header()` checked that `buf` is long enough.
+    let end = *offset + remaining_len;
+    let buf = &buf[..end];
+    let packet = match header.typ {
         PacketType::Pingreq => Packet::Pingreq,
         PacketType::Pingresp => Packet::Pingresp,
         PacketType::Disconnect => Packet::Disconnect,
-        PacketType::Connect => Connect::from_buffer(buf, offset)?.into(),
+        PacketType::Connect => Connect::from_buffer(buf, offset, ctx)?.into(),
         PacketType::Connack => Connack::from_buffer(buf, offset)?.into(),
-        PacketType::Publish => Publish::from_buffer(&header, remaining_len, buf, offset)?.into(),
+        PacketType::Publish => {
+            Publish::from_buffer(&header, remaining_len, buf, offset, ctx)?.into()
+        }
         PacketType::Puback => Packet::Puback(Pid::from_buffer(buf, offset)?),
         PacketType::Pubrec => Packet::Pubrec(Pid::from_buffer(buf, offset)?),
         PacketType::Pubrel => Packet::Pubrel(Pid::from_buffer(buf, offset)?),
         PacketType::Pubcomp => Packet::Pubcomp(Pid::from_buffer(buf, offset)?),
-        PacketType::Subscribe => Subscribe::from_buffer(remaining_len, buf, offset)?.into(),
-        PacketType::Suback => Suback::from_buffer(remaining_len, buf, offset)?.into(),
-        PacketType::Unsubscribe => Unsubscribe::from_buffer(remaining_len, buf, offset)?.into(),
+        PacketType::Subscribe => {
+            Packet::Subscribe(Subscribe::from_buffer(remaining_len, buf, offset, ctx)?)
+        }
+        PacketType::Suback => Packet::Suback(Suback::from_buffer(remaining_len, buf, offset)?),
+        PacketType::Unsubscribe => {
+            Packet::Unsubscribe(Unsubscribe::from_buffer(remaining_len, buf, offset, ctx)?)
+        }
         PacketType::Unsuback => Packet::Unsuback(Pid::from_buffer(buf, offset)?),
-    })
+    };
+    if *offset != end {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(packet)
 }
 
 /// Read the parsed header and remaining_len from the buffer. Only return Some() and advance the
 /// buffer position if there is enough data in the buffer to read the full packet.
-pub(crate) fn read_header<'a>(
-    buf: &'a [u8],
+pub(crate) fn read_header(
+    buf: &[u8],
     offset: &mut usize,
 ) -> Result<Option<(Header, usize)>, Error> {
+    match peek_header(&buf[*offset..])? {
+        Some(fixed) if buf.len() >= *offset + fixed.packet_len() => {
+            *offset += fixed.header_len;
+            Ok(Some((fixed.header(), fixed.remaining_len)))
+        }
+        // Won't be able to read full packet
+        _ => Ok(None),
+    }
+}
+
+/// Fixed header of an MQTT packet ([MQTT 2.2]).
+///
+/// Returned by [`peek_header()`]. The `dup`, `qos` and `retain` flags are only meaningful for
+/// [`Publish`] packets.
+///
+/// [MQTT 2.2]: http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718020
+/// [`peek_header()`]: fn.peek_header.html
+/// [`Publish`]: struct.Publish.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedHeader {
+    pub typ: PacketType,
+    pub dup: bool,
+    pub qos: QoS,
+    pub retain: bool,
+    /// Length of the packet body.
+    pub remaining_len: usize,
+    /// Length of the fixed header itself (2 to 5 bytes).
+    pub header_len: usize,
+}
+
+impl FixedHeader {
+    /// Total length of the packet, fixed header included.
+    pub fn packet_len(&self) -> usize {
+        self.header_len + self.remaining_len
+    }
+
+    pub(crate) fn header(&self) -> Header {
+        Header {
+            typ: self.typ,
+            dup: self.dup,
+            qos: self.qos,
+            retain: self.retain,
+        }
+    }
+}
+
+/// Parse the fixed header at the start of a slice, without decoding the rest of the packet.
+///
+/// Returns `Ok(None)` if the fixed header is incomplete. The packet body doesn't need to be
+/// available yet. This is useful to route or filter raw packets cheaply:
+///
+/// ```
+/// # use mqttrs::*;
+/// // Start of a Publish packet with qos=1 and remaining_len=200.
+/// let buf = [0b00110010, 0xc8, 0x01, 0, 4];
+/// let header = peek_header(&buf).unwrap().unwrap();
+/// assert_eq!(header.typ, PacketType::Publish);
+/// assert_eq!(header.qos, QoS::AtLeastOnce);
+/// assert_eq!(header.remaining_len, 200);
+/// assert_eq!(header.header_len, 3);
+/// assert_eq!(header.packet_len(), 203);
+/// ```
+pub fn peek_header(buf: &[u8]) -> Result<Option<FixedHeader>, Error> {
+    Ok(parse_header(buf, false)?.map(|(fixed, _)| fixed))
+}
+
+/// Parse the fixed header, also returning whether its flags are valid. Invalid flags are an error
+/// unless `lenient` is true.
+pub(crate) fn parse_header(
+    buf: &[u8],
+    lenient: bool,
+) -> Result<Option<(FixedHeader, bool)>, Error> {
     let mut len: usize = 0;
     for pos in 0..=3 {
-        if buf.len() > *offset + pos + 1 {
-            let byte = buf[*offset + pos + 1];
+        if let Some(&byte) = buf.get(pos + 1) {
             len += (byte as usize & 0x7F) << (pos * 7);
             if (byte & 0x80) == 0 {
                 // Continuation bit == 0, length is parsed
-                if buf.len() < *offset + 2 + pos + len {
-                    // Won't be able to read full packet
-                    return Ok(None);
-                }
-                // Parse header byte, skip past the header, and return
-                let header = Header::new(buf[*offset])?;
-                *offset += pos + 2;
-                return Ok(Some((header, len)));
+                let (header, flags_ok) = if lenient {
+                    Header::new_lenient(buf[0])?
+                } else {
+                    (Header::new(buf[0])?, true)
+                };
+                let fixed = FixedHeader {
+                    typ: header.typ,
+                    dup: header.dup,
+                    qos: header.qos,
+                    retain: header.retain,
+                    remaining_len: len,
+                    header_len: pos + 2,
+                };
+                return Ok(Some((fixed, flags_ok)));
             }
         } else {
             // Couldn't read full length
@@ -126,50 +758,93 @@ pub(crate) struct Header {
 }
 impl Header {
     pub fn new(hd: u8) -> Result<Header, Error> {
-        let (typ, flags_ok) = match hd >> 4 {
-            1 => (PacketType::Connect, hd & 0b1111 == 0),
-            2 => (PacketType::Connack, hd & 0b1111 == 0),
-            3 => (PacketType::Publish, true),
-            4 => (PacketType::Puback, hd & 0b1111 == 0),
-            5 => (PacketType::Pubrec, hd & 0b1111 == 0),
-            6 => (PacketType::Pubrel, hd & 0b1111 == 0b0010),
-            7 => (PacketType::Pubcomp, hd & 0b1111 == 0),
-            8 => (PacketType::Subscribe, hd & 0b1111 == 0b0010),
-            9 => (PacketType::Suback, hd & 0b1111 == 0),
-            10 => (PacketType::Unsubscribe, hd & 0b1111 == 0b0010),
-            11 => (PacketType::Unsuback, hd & 0b1111 == 0),
-            12 => (PacketType::Pingreq, hd & 0b1111 == 0),
-            13 => (PacketType::Pingresp, hd & 0b1111 == 0),
-            14 => (PacketType::Disconnect, hd & 0b1111 == 0),
-            _ => (PacketType::Connect, false),
-        };
-        if !flags_ok {
-            return Err(Error::InvalidHeader);
+        match Header::new_lenient(hd)? {
+            (header, true) => Ok(header),
+            (_, false) => Err(Error::InvalidHeader),
         }
-        Ok(Header {
+    }
+
+    /// Parse the header byte, also returning whether its flags are valid. If they aren't, the
+    /// header is returned with the flags required by the packet type.
+    pub fn new_lenient(hd: u8) -> Result<(Header, bool), Error> {
+        let (typ, flags) = match hd >> 4 {
+            1 => (PacketType::Connect, 0),
+            2 => (PacketType::Connack, 0),
+            3 => (PacketType::Publish, hd & 0b1111),
+            4 => (PacketType::Puback, 0),
+            5 => (PacketType::Pubrec, 0),
+            6 => (PacketType::Pubrel, 0b0010),
+            7 => (PacketType::Pubcomp, 0),
+            8 => (PacketType::Subscribe, 0b0010),
+            9 => (PacketType::Suback, 0),
+            10 => (PacketType::Unsubscribe, 0b0010),
+            11 => (PacketType::Unsuback, 0),
+            12 => (PacketType::Pingreq, 0),
+            13 => (PacketType::Pingresp, 0),
+            14 => (PacketType::Disconnect, 0),
+            _ => return Err(Error::InvalidHeader),
+        };
+        let header = Header {
             typ,
-            dup: hd & 0b1000 != 0,
-            qos: QoS::from_u8((hd & 0b110) >> 1)?,
-            retain: hd & 1 == 1,
-        })
+            dup: flags & 0b1000 != 0,
+            qos: QoS::from_u8((flags & 0b110) >> 1)?,
+            retain: flags & 1 == 1,
+        };
+        Ok((header, hd & 0b1111 == flags))
     }
 }
 
-pub(crate) fn read_str<'a>(buf: &'a [u8], offset: &mut usize) -> Result<&'a str, Error> {
-    core::str::from_utf8(read_bytes(buf, offset)?).map_err(|e| Error::InvalidString(e))
+/// Read a string, or in lenient mode, the valid UTF-8 prefix of an invalid string.
+pub(crate) fn read_str<'a>(
+    buf: &'a [u8],
+    offset: &mut usize,
+    field: Field,
+    ctx: &mut DecodeCtx<'a, '_>,
+) -> Result<&'a str, Error> {
+    let bytes = read_bytes(buf, offset)?;
+    match core::str::from_utf8(bytes) {
+        Ok(s) => match check_str(s, field) {
+            Err(_) if ctx.lenient() => {
+                ctx.warn(Warning::InvalidString { field, raw: bytes });
+                Ok(s)
+            }
+            res => res.map(|_| s),
+        },
+        Err(e) if ctx.lenient() => {
+            ctx.warn(Warning::InvalidString { field, raw: bytes });
+            Ok(core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default())
+        }
+        Err(e) => Err(Error::InvalidString(field, e)),
+    }
 }
 
 pub(crate) fn read_bytes<'a>(buf: &'a [u8], offset: &mut usize) -> Result<&'a [u8], Error> {
-    if buf[*offset..].len() < 2 {
-        return Err(Error::InvalidLength);
+    let len = read_u16(buf, offset)? as usize;
+    match buf.get(*offset..*offset + len) {
+        Some(bytes) => {
+            *offset += len;
+            Ok(bytes)
+        }
+        None => Err(Error::TruncatedBody),
     }
-    let len = ((buf[*offset] as usize) << 8) | buf[*offset + 1] as usize;
-    *offset += 2;
-    if len > buf[*offset..].len() {
-        Err(Error::InvalidLength)
-    } else {
-        let bytes = &buf[*offset..*offset + len];
-        *offset += len;
-        Ok(bytes)
+}
+
+pub(crate) fn read_u8(buf: &[u8], offset: &mut usize) -> Result<u8, Error> {
+    match buf.get(*offset) {
+        Some(&byte) => {
+            *offset += 1;
+            Ok(byte)
+        }
+        None => Err(Error::TruncatedBody),
+    }
+}
+
+pub(crate) fn read_u16(buf: &[u8], offset: &mut usize) -> Result<u16, Error> {
+    match buf.get(*offset..*offset + 2) {
+        Some(bytes) => {
+            *offset += 2;
+            Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+        }
+        None => Err(Error::TruncatedBody),
     }
 }
@@ -1,7 +1,11 @@
 use crate::*;
-use bytes::BytesMut;
+use core::convert::TryFrom;
+use proptest::{collection::vec, prelude::*};
 use subscribe::LimitedString;
 
+#[cfg(feature = "std")]
+use bytes::BytesMut;
+
 macro_rules! header {
     ($t:ident, $d:expr, $q:ident, $r:expr) => {
         decoder::Header {
@@ -13,6 +17,7 @@ macro_rules! header {
     };
 }
 
+#[cfg(feature = "std")]
 fn bm(d: &[u8]) -> BytesMut {
     BytesMut::from(d)
 }
@@ -53,14 +58,9 @@ fn header_firstbyte() {
             None if ((n & 0b110) == 0b110) && (n >> 4 == 3) => Err(Error::InvalidQos(3)),
             None => Err(Error::InvalidHeader),
         };
-        let mut buf: &[u8] = &[n, 0];
+        let buf: &[u8] = &[n, 0];
         let mut offset = 0;
-        assert_eq!(
-            res,
-            decoder::read_header(&mut buf, &mut offset),
-            "{:08b}",
-            n
-        );
+        assert_eq!(res, decoder::read_header(buf, &mut offset), "{:08b}", n);
         if res.is_ok() {
             assert_eq!(offset, 2);
         } else {
@@ -87,9 +87,9 @@ fn header_len() {
     ] {
         let offset_expectation = bytes.len();
         bytes.resize(buflen, 0);
-        let mut slice_buf = bytes.as_slice();
+        let slice_buf = bytes.as_slice();
         let mut offset = 0;
-        assert_eq!(res, decoder::read_header(&mut slice_buf, &mut offset));
+        assert_eq!(res, decoder::read_header(slice_buf, &mut offset));
         match res {
             Ok(Some(_)) => assert_eq!(offset, offset_expectation),
             _ => assert_eq!(offset, 0)
@@ -99,46 +99,149 @@ fn header_len() {
 
 #[test]
 fn non_utf8_string() {
-    let mut data: &[u8] = &[
+    let data: &[u8] = &[
         0b00110000, 10, // type=Publish, remaining_len=10
-        0x00, 0x03, 'a' as u8, '/' as u8, 0xc0 as u8, // Topic with Invalid utf8
-        'h' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8, // payload
+        0x00, 0x03, b'a', b'/', 0xc0_u8, // Topic with Invalid utf8
+        b'h', b'e', b'l', b'l', b'o', // payload
+    ];
+    assert!(matches!(
+        decode_slice(data),
+        Err(Error::InvalidString(Field::TopicName, _))
+    ));
+}
+
+#[test]
+fn null_char_string() {
+    let data: &[u8] = &[
+        0b00110000, 10, // type=Publish, remaining_len=10
+        0x00, 0x03, b'a', 0x00, b'b', // Topic with U+0000
+        b'h', b'e', b'l', b'l', b'o', // payload
+    ];
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicName)),
+        decode_slice(data)
+    );
+
+    let data: &[u8] = &[
+        0b00010000, 15, // type=Connect, remaining_len=15
+        0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, // protocol
+        0b00000000, 0x00, 0x0a, // flags, keep alive
+        0x00, 0x03, b'i', 0x00, b'd', // client id with U+0000
+    ];
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::ClientId)),
+        decode_slice(data)
+    );
+
+    let data: &[u8] = &[
+        0b10100010, 7, // type=Unsubscribe, remaining_len=7
+        0x00, 0x0a, // pid
+        0x00, 0x03, b'a', b'/', 0x00, // topic filter with U+0000
+    ];
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicFilter)),
+        decode_slice(data)
+    );
+
+    let lenient = DecodeOptions::new().lenient(true);
+    let mut warnings = Warnings::new();
+    let data: &[u8] = &[
+        0b00110000, 10, 0x00, 0x03, b'a', 0x00, b'b', b'h', b'e', b'l', b'l', b'o',
     ];
-    assert!(match decode_slice(&mut data) {
-        Err(Error::InvalidString(_)) => true,
-        _ => false,
-    });
+    match decode_slice_with_warnings(data, &lenient, &mut warnings) {
+        Ok(Some((Packet::Publish(p), 12))) => assert_eq!(p.topic_name, "a\0b"),
+        other => panic!("Failed decode: {:?}", other),
+    }
+    assert_eq!(
+        &warnings[..],
+        &[Warning::InvalidString {
+            field: Field::TopicName,
+            raw: b"a\0b"
+        }]
+    );
+}
+
+#[test]
+fn control_and_non_char_string() {
+    let lenient = DecodeOptions::new().lenient(true);
+    for s in &[
+        "a\tb",
+        "\u{7f}",
+        "a\u{85}",
+        "\u{fdd0}",
+        "\u{ffff}",
+        "b/\u{1fffe}",
+    ] {
+        let mut data = std::vec![0b00110000, 2 + s.len() as u8, 0, s.len() as u8];
+        data.extend_from_slice(s.as_bytes());
+        assert_eq!(
+            Err(Error::DisallowedCodePoint(Field::TopicName)),
+            decode_slice(&data),
+            "{:?}",
+            s
+        );
+        let mut warnings = Warnings::new();
+        match decode_slice_with_warnings(&data, &lenient, &mut warnings) {
+            Ok(Some((Packet::Publish(p), _))) => assert_eq!(&p.topic_name, s),
+            other => panic!("Failed decode: {:?}", other),
+        }
+        assert_eq!(
+            &warnings[..],
+            &[Warning::InvalidString {
+                field: Field::TopicName,
+                raw: s.as_bytes()
+            }]
+        );
+    }
+
+    // Other code points are allowed, including U+FFFD and private use ones.
+    for s in &[
+        "a b~",
+        "é/\u{a0}",
+        "\u{fdcf}\u{fdf0}\u{fffd}",
+        "\u{e000}\u{10fffd}",
+    ] {
+        let mut data = std::vec![0b00110000, 2 + s.len() as u8, 0, s.len() as u8];
+        data.extend_from_slice(s.as_bytes());
+        match decode_slice(&data) {
+            Ok(Some(Packet::Publish(p))) => assert_eq!(&p.topic_name, s),
+            other => panic!("Failed decode of {:?}: {:?}", s, other),
+        }
+    }
 }
 
 /// Validity of remaining_len is tested exhaustively elsewhere, this is for inner lengths, which
 /// are rarer.
 #[test]
 fn inner_length_too_long() {
-    let mut data = bm(&[
-        0b00010000, 20, // Connect packet, remaining_len=20
-        0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 0x04, 0b01000000, // +password
-        0x00, 0x0a, // keepalive 10 sec
-        0x00, 0x04, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, // client_id
-        0x00, 0x03, 'm' as u8, 'q' as u8, // password with invalid length
-    ]);
-    assert_eq!(Err(Error::InvalidLength), decode_slice(&mut data));
+    #[cfg(feature = "std")]
+    {
+        let data = bm(&[
+            0b00010000, 20, // Connect packet, remaining_len=20
+            0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0b10000000, // +username
+            0x00, 0x0a, // keepalive 10 sec
+            0x00, 0x04, b't', b'e', b's', b't', // client_id
+            0x00, 0x03, b'm', b'q', // username with invalid length
+        ]);
+        assert_eq!(Err(Error::TruncatedBody), decode_slice(&data));
+    }
 
-    let mut slice: &[u8] = &[
+    let slice: &[u8] = &[
         0b00010000, 20, // Connect packet, remaining_len=20
-        0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 0x04, 0b01000000, // +password
+        0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0b10000000, // +username
         0x00, 0x0a, // keepalive 10 sec
-        0x00, 0x04, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, // client_id
-        0x00, 0x03, 'm' as u8, 'q' as u8, // password with invalid length
+        0x00, 0x04, b't', b'e', b's', b't', // client_id
+        0x00, 0x03, b'm', b'q', // username with invalid length
     ];
 
-    assert_eq!(Err(Error::InvalidLength), decode_slice(&mut slice));
+    assert_eq!(Err(Error::TruncatedBody), decode_slice(slice));
     // assert_eq!(slice, []);
 }
 
 #[test]
 fn test_half_connect() {
-    let mut data: &[u8] = &[
-        0b00010000, 39, 0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 0x04,
+    let data: &[u8] = &[
+        0b00010000, 39, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04,
         0b11001110, // +username, +password, -will retain, will qos=1, +last_will, +clean_session
         0x00,
         0x0a, // 10 sec
@@ -149,41 +252,119 @@ fn test_half_connect() {
               // 0x00, 0x04, 'r' as u8, 'u' as u8, 's' as u8, 't' as u8, // username = 'rust'
               // 0x00, 0x02, 'm' as u8, 'q' as u8, // password = 'mq'
     ];
-    assert_eq!(Ok(None), decode_slice(&mut data));
+    assert_eq!(Ok(None), decode_slice(data));
     assert_eq!(12, data.len());
 }
 
 #[test]
 fn test_connect_wrong_version() {
-    let mut data: &[u8] = &[
-        0b00010000, 39, 0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 0x01,
+    let data: &[u8] = &[
+        0b00010000, 39, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x01,
         0b11001110, // +username, +password, -will retain, will qos=1, +last_will, +clean_session
         0x00, 0x0a, // 10 sec
-        0x00, 0x04, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, // client_id
-        0x00, 0x02, '/' as u8, 'a' as u8, // will topic = '/a'
-        0x00, 0x07, 'o' as u8, 'f' as u8, 'f' as u8, 'l' as u8, 'i' as u8, 'n' as u8,
-        'e' as u8, // will msg = 'offline'
-        0x00, 0x04, 'r' as u8, 'u' as u8, 's' as u8, 't' as u8, // username = 'rust'
-        0x00, 0x02, 'm' as u8, 'q' as u8, // password = 'mq'
+        0x00, 0x04, b't', b'e', b's', b't', // client_id
+        0x00, 0x02, b'/', b'a', // will topic = '/a'
+        0x00, 0x07, b'o', b'f', b'f', b'l', b'i', b'n', b'e', // will msg = 'offline'
+        0x00, 0x04, b'r', b'u', b's', b't', // username = 'rust'
+        0x00, 0x02, b'm', b'q', // password = 'mq'
     ];
     assert!(
-        decode_slice(&mut data).is_err(),
+        decode_slice(data).is_err(),
         "Unknown version should return error"
     );
 }
 
+#[test]
+fn test_connect_long_protocol_name() {
+    let data: &[u8] = &[
+        0b00010000, 22, 0x00, 0x0c, b'M', b'Q', b'I', b's', b'd', b'p', b'M', b'Q', b'I', b's',
+        b'd', b'p', 0x03, 0b00000010, 0x00, 0x0a, // keep alive
+        0x00, 0x02, b'i', b'd', // client_id
+    ];
+    #[cfg(feature = "std")]
+    assert_eq!(
+        Err(Error::InvalidProtocol("MQIsdpMQIsdp".into(), 3)),
+        decode_slice(data)
+    );
+    // Without std, the name is truncated on a char boundary instead of panicking.
+    #[cfg(not(feature = "std"))]
+    assert_eq!(
+        Err(Error::InvalidProtocol("MQIsdpMQIs".into(), 3)),
+        decode_slice(data)
+    );
+
+    let data: &[u8] = &[
+        0b00010000, 23, 0x00, 0x0d, b'M', b'Q', b'T', b'T', b'x', 0xc3, 0xa9, 0xc3, 0xa9, 0xc3,
+        0xa9, 0xc3, 0xa9, 0x04, 0b00000010, 0x00, 0x0a, // keep alive
+        0x00, 0x02, b'i', b'd', // client_id
+    ];
+    #[cfg(feature = "std")]
+    assert_eq!(
+        Err(Error::InvalidProtocol("MQTTxéééé".into(), 4)),
+        decode_slice(data)
+    );
+    #[cfg(not(feature = "std"))]
+    assert_eq!(
+        Err(Error::InvalidProtocol("MQTTxéé".into(), 4)),
+        decode_slice(data)
+    );
+}
+
+#[test]
+fn test_connect_invalid_flags() {
+    let connect = |flags: u8| -> std::vec::Vec<u8> {
+        let mut data = vec![0b00010000, 16, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04];
+        data.extend_from_slice(&[flags, 0x00, 0x0a, 0x00, 0x04, b't', b'e', b's', b't']);
+        data
+    };
+    // Reserved flag
+    assert_eq!(
+        Err(Error::ReservedConnectFlag),
+        decode_slice(&connect(0b00000011))
+    );
+    // Will QoS and will retain without will flag
+    assert_eq!(
+        Err(Error::InvalidWillFlags),
+        decode_slice(&connect(0b00001010))
+    );
+    assert_eq!(
+        Err(Error::InvalidWillFlags),
+        decode_slice(&connect(0b00100010))
+    );
+    // Password without username (with an empty password)
+    let mut data = connect(0b01000010);
+    data[1] += 2;
+    data.extend_from_slice(&[0x00, 0x00]);
+    assert_eq!(Err(Error::PasswordWithoutUsername), decode_slice(&data));
+
+    // Tolerated in lenient mode
+    let lenient = DecodeOptions::new().lenient(true);
+    let data = connect(0b00101011);
+    let mut warnings = Warnings::new();
+    match decode_slice_with_warnings(&data, &lenient, &mut warnings) {
+        Ok(Some((Packet::Connect(c), 18))) => {
+            assert!(c.clean_session);
+            assert_eq!(c.last_will, None);
+        }
+        other => panic!("Failed decode: {:?}", other),
+    }
+    assert_eq!(
+        &warnings[..],
+        &[Warning::InvalidConnectFlags { flags: 0b00101011 }]
+    );
+}
+
 #[test]
 fn test_connect() {
-    let mut data: &[u8] = &[
-        0b00010000, 39, 0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8, 0x04,
+    let data: &[u8] = &[
+        0b00010000, 39, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04,
         0b11001110, // +username, +password, -will retain, will qos=1, +last_will, +clean_session
         0x00, 0x0a, // 10 sec
-        0x00, 0x04, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, // client_id
-        0x00, 0x02, '/' as u8, 'a' as u8, // will topic = '/a'
-        0x00, 0x07, 'o' as u8, 'f' as u8, 'f' as u8, 'l' as u8, 'i' as u8, 'n' as u8,
-        'e' as u8, // will msg = 'offline'
-        0x00, 0x04, 'r' as u8, 'u' as u8, 's' as u8, 't' as u8, // username = 'rust'
-        0x00, 0x02, 'm' as u8, 'q' as u8, // password = 'mq'
+        0x00, 0x04, b't', b'e', b's', b't', // client_id
+        0x00, 0x02, b'/', b'a', // will topic = '/a'
+        0x00, 0x07, b'o', b'f', b'f', b'l', b'i', b'n', b'e', // will msg = 'offline'
+        0x00, 0x04, b'r', b'u', b's', b't', // username = 'rust'
+        0x00, 0x02, b'm', b'q', // password = 'mq'
     ];
     let pkt = Connect {
         protocol: Protocol::MQTT311,
@@ -201,15 +382,15 @@ fn test_connect() {
     };
 
     let packet_buf = &mut [0u8; 64];
-    assert_eq!(clone_packet(&mut data, &mut packet_buf[..]).unwrap(), 41);
+    assert_eq!(clone_packet(data, &mut packet_buf[..]).unwrap(), 41);
     assert_eq!(Ok(Some(pkt.into())), decode_slice(packet_buf));
     // assert_eq!(data.len(), 0);
 }
 
 #[test]
 fn test_connack() {
-    let mut data: &[u8] = &[0b00100000, 2, 0b00000000, 0b00000001];
-    let d = decode_slice(&mut data).unwrap();
+    let data: &[u8] = &[0b00100000, 2, 0b00000000, 0b00000001];
+    let d = decode_slice(data).unwrap();
     match d {
         Some(Packet::Connack(c)) => {
             let o = Connack {
@@ -225,42 +406,40 @@ fn test_connack() {
 
 #[test]
 fn test_ping_req() {
-    let mut data: &[u8] = &[0b11000000, 0b00000000];
-    assert_eq!(Ok(Some(Packet::Pingreq)), decode_slice(&mut data));
+    let data: &[u8] = &[0b11000000, 0b00000000];
+    assert_eq!(Ok(Some(Packet::Pingreq)), decode_slice(data));
 }
 
 #[test]
 fn test_ping_resp() {
-    let mut data: &[u8] = &[0b11010000, 0b00000000];
-    assert_eq!(Ok(Some(Packet::Pingresp)), decode_slice(&mut data));
+    let data: &[u8] = &[0b11010000, 0b00000000];
+    assert_eq!(Ok(Some(Packet::Pingresp)), decode_slice(data));
 }
 
 #[test]
 fn test_disconnect() {
-    let mut data: &[u8] = &[0b11100000, 0b00000000];
-    assert_eq!(Ok(Some(Packet::Disconnect)), decode_slice(&mut data));
+    let data: &[u8] = &[0b11100000, 0b00000000];
+    assert_eq!(Ok(Some(Packet::Disconnect)), decode_slice(data));
 }
 
 #[test]
 #[ignore]
 fn test_offset_start() {
-    let mut data: &[u8] = &[
-        1, 2, 3, 0b00110000, 10, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8, //
-        0b00111000, 10, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8, //
-        0b00111101, 12, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 0, 10, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8,
+    let data: &[u8] = &[
+        1, 2, 3, 0b00110000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l',
+        b'o', //
+        0b00111000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', //
+        0b00111101, 12, 0x00, 0x03, b'a', b'/', b'b', 0, 10, b'h', b'e', b'l', b'l', b'o',
     ];
 
     let packet_buf = &mut [0u8; 64];
-    assert_eq!(clone_packet(&mut data, &mut packet_buf[..]).unwrap(), 12);
+    assert_eq!(clone_packet(data, &mut packet_buf[..]).unwrap(), 12);
     assert_eq!(data.len(), 29);
 
     match decode_slice(packet_buf) {
         Ok(Some(Packet::Publish(p))) => {
-            assert_eq!(p.dup, false);
-            assert_eq!(p.retain, false);
+            assert!(!p.dup);
+            assert!(!p.retain);
             assert_eq!(p.qospid, QosPid::AtMostOnce);
             assert_eq!(p.topic_name, "a/b");
             assert_eq!(core::str::from_utf8(p.payload).unwrap(), "hello");
@@ -272,30 +451,27 @@ fn test_offset_start() {
 #[test]
 #[ignore]
 fn test_publish() {
-    let mut data: &[u8] = &[
-        0b00110000, 10, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8, //
-        0b00111000, 10, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8, //
-        0b00111101, 12, 0x00, 0x03, 'a' as u8, '/' as u8, 'b' as u8, 0, 10, 'h' as u8, 'e' as u8,
-        'l' as u8, 'l' as u8, 'o' as u8,
+    let data: &[u8] = &[
+        0b00110000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', //
+        0b00111000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', //
+        0b00111101, 12, 0x00, 0x03, b'a', b'/', b'b', 0, 10, b'h', b'e', b'l', b'l', b'o',
     ];
 
     let mut offset = 0;
     assert_eq!(
-        decoder::read_header(&data, &mut offset).unwrap(),
+        decoder::read_header(data, &mut offset).unwrap(),
         Some((decoder::Header::new(0b00110000).unwrap(), 10))
     );
     assert_eq!(data.len(), 38);
 
     let packet_buf = &mut [0u8; 64];
-    assert_eq!(clone_packet(&mut data, &mut packet_buf[..]).unwrap(), 12);
+    assert_eq!(clone_packet(data, &mut packet_buf[..]).unwrap(), 12);
     // assert_eq!(data.len(), 26);
 
     match decode_slice(packet_buf) {
         Ok(Some(Packet::Publish(p))) => {
-            assert_eq!(p.dup, false);
-            assert_eq!(p.retain, false);
+            assert!(!p.dup);
+            assert!(!p.retain);
             assert_eq!(p.qospid, QosPid::AtMostOnce);
             assert_eq!(p.topic_name, "a/b");
             assert_eq!(core::str::from_utf8(p.payload).unwrap(), "hello");
@@ -304,12 +480,12 @@ fn test_publish() {
     }
 
     let packet_buf2 = &mut [0u8; 64];
-    assert_eq!(clone_packet(&mut data, &mut packet_buf2[..]).unwrap(), 12);
+    assert_eq!(clone_packet(data, &mut packet_buf2[..]).unwrap(), 12);
     // assert_eq!(data.len(), 14);
     match decode_slice(packet_buf2) {
         Ok(Some(Packet::Publish(p))) => {
-            assert_eq!(p.dup, true);
-            assert_eq!(p.retain, false);
+            assert!(p.dup);
+            assert!(!p.retain);
             assert_eq!(p.qospid, QosPid::AtMostOnce);
             assert_eq!(p.topic_name, "a/b");
             assert_eq!(core::str::from_utf8(p.payload).unwrap(), "hello");
@@ -318,13 +494,13 @@ fn test_publish() {
     }
 
     let packet_buf3 = &mut [0u8; 64];
-    assert_eq!(clone_packet(&mut data, &mut packet_buf3[..]).unwrap(), 14);
+    assert_eq!(clone_packet(data, &mut packet_buf3[..]).unwrap(), 14);
     // assert_eq!(data.len(), 0);
 
     match decode_slice(packet_buf3) {
         Ok(Some(Packet::Publish(p))) => {
-            assert_eq!(p.dup, true);
-            assert_eq!(p.retain, true);
+            assert!(p.dup);
+            assert!(p.retain);
             assert_eq!(p.qospid, QosPid::from_u8u16(2, 10));
             assert_eq!(p.topic_name, "a/b");
             assert_eq!(core::str::from_utf8(p.payload).unwrap(), "hello");
@@ -335,8 +511,8 @@ fn test_publish() {
 
 #[test]
 fn test_pub_ack() {
-    let mut data: &[u8] = &[0b01000000, 0b00000010, 0, 10];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b01000000, 0b00000010, 0, 10];
+    match decode_slice(data) {
         Ok(Some(Packet::Puback(a))) => assert_eq!(a.get(), 10),
         other => panic!("Failed decode: {:?}", other),
     };
@@ -344,8 +520,8 @@ fn test_pub_ack() {
 
 #[test]
 fn test_pub_rec() {
-    let mut data: &[u8] = &[0b01010000, 0b00000010, 0, 10];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b01010000, 0b00000010, 0, 10];
+    match decode_slice(data) {
         Ok(Some(Packet::Pubrec(a))) => assert_eq!(a.get(), 10),
         other => panic!("Failed decode: {:?}", other),
     };
@@ -353,8 +529,8 @@ fn test_pub_rec() {
 
 #[test]
 fn test_pub_rel() {
-    let mut data: &[u8] = &[0b01100010, 0b00000010, 0, 10];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b01100010, 0b00000010, 0, 10];
+    match decode_slice(data) {
         Ok(Some(Packet::Pubrel(a))) => assert_eq!(a.get(), 10),
         other => panic!("Failed decode: {:?}", other),
     };
@@ -362,8 +538,8 @@ fn test_pub_rel() {
 
 #[test]
 fn test_pub_comp() {
-    let mut data: &[u8] = &[0b01110000, 0b00000010, 0, 10];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b01110000, 0b00000010, 0, 10];
+    match decode_slice(data) {
         Ok(Some(Packet::Pubcomp(a))) => assert_eq!(a.get(), 10),
         other => panic!("Failed decode: {:?}", other),
     };
@@ -371,17 +547,15 @@ fn test_pub_comp() {
 
 #[test]
 fn test_subscribe() {
-    let mut data: &[u8] = &[
-        0b10000010, 8, 0, 10, 0, 3, 'a' as u8, '/' as u8, 'b' as u8, 0,
-    ];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b10000010, 8, 0, 10, 0, 3, b'a', b'/', b'b', 0];
+    match decode_slice(data) {
         Ok(Some(Packet::Subscribe(s))) => {
             assert_eq!(s.pid.get(), 10);
             let t = SubscribeTopic {
                 topic_path: LimitedString::from("a/b"),
                 qos: QoS::AtMostOnce,
             };
-            assert_eq!(s.topics.get(0), Some(&t));
+            assert_eq!(s.topics.first(), Some(&t));
         }
         other => panic!("Failed decode: {:?}", other),
     }
@@ -389,12 +563,12 @@ fn test_subscribe() {
 
 #[test]
 fn test_suback() {
-    let mut data: &[u8] = &[0b10010000, 3, 0, 10, 0b00000010];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b10010000, 3, 0, 10, 0b00000010];
+    match decode_slice(data) {
         Ok(Some(Packet::Suback(s))) => {
             assert_eq!(s.pid.get(), 10);
             assert_eq!(
-                s.return_codes.get(0),
+                s.return_codes.first(),
                 Some(&SubscribeReturnCodes::Success(QoS::ExactlyOnce))
             );
         }
@@ -404,23 +578,701 @@ fn test_suback() {
 
 #[test]
 fn test_unsubscribe() {
-    let mut data: &[u8] = &[0b10100010, 5, 0, 10, 0, 1, 'a' as u8];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b10100010, 5, 0, 10, 0, 1, b'a'];
+    match decode_slice(data) {
         Ok(Some(Packet::Unsubscribe(a))) => {
             assert_eq!(a.pid.get(), 10);
-            assert_eq!(a.topics.get(0), Some(&LimitedString::from("a")));
+            assert_eq!(a.topics.first(), Some(&LimitedString::from("a")));
         }
         other => panic!("Failed decode: {:?}", other),
     }
 }
 
+#[test]
+fn test_empty_payload() {
+    assert_eq!(
+        Err(Error::EmptyPayload),
+        decode_slice(&[0b10000010, 2, 0, 10])
+    );
+    assert_eq!(
+        Err(Error::EmptyPayload),
+        decode_slice(&[0b10010000, 2, 0, 10])
+    );
+    assert_eq!(
+        Err(Error::EmptyPayload),
+        decode_slice(&[0b10100010, 2, 0, 10])
+    );
+}
+
+#[test]
+fn test_subscribe_reserved_qos_bits() {
+    let data: &[u8] = &[0b10000010, 8, 0, 10, 0, 3, b'a', b'/', b'b', 0b00000101];
+    assert_eq!(Err(Error::InvalidQos(0b00000101)), decode_slice(data));
+    let data: &[u8] = &[0b10000010, 8, 0, 10, 0, 3, b'a', b'/', b'b', 0b10000001];
+    assert_eq!(Err(Error::InvalidQos(0b10000001)), decode_slice(data));
+}
+
 #[test]
 fn test_unsub_ack() {
-    let mut data: &[u8] = &[0b10110000, 2, 0, 10];
-    match decode_slice(&mut data) {
+    let data: &[u8] = &[0b10110000, 2, 0, 10];
+    match decode_slice(data) {
         Ok(Some(Packet::Unsuback(p))) => {
             assert_eq!(p.get(), 10);
         }
         other => panic!("Failed decode: {:?}", other),
     }
 }
+
+#[test]
+fn test_decode_slice_with_len() {
+    let data: &[u8] = &[
+        0b01000000, 0b00000010, 0, 10, // Puback
+        0b11000000, 0b00000000, // Pingreq
+    ];
+    assert_eq!(
+        Ok(Some((Packet::Puback(Pid::try_from(10).unwrap()), 4))),
+        decode_slice_with_len(data)
+    );
+    assert_eq!(
+        Ok(Some((Packet::Pingreq, 2))),
+        decode_slice_with_len(&data[4..])
+    );
+    assert_eq!(Ok(None), decode_slice_with_len(&data[..3]));
+}
+
+#[test]
+fn test_packet_iter() {
+    let data: &[u8] = &[
+        0b01000000, 0b00000010, 0, 10, // Puback
+        0b11000000, 0b00000000, // Pingreq
+        0b00110000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', // Publish
+        0b10110000, 2, 0, // partial Unsuback
+    ];
+    let mut iter = PacketIter::new(data);
+    assert_eq!(
+        Some(Ok(Packet::Puback(Pid::try_from(10).unwrap()))),
+        iter.next()
+    );
+    assert_eq!(Some(Ok(Packet::Pingreq)), iter.next());
+    match iter.next() {
+        Some(Ok(Packet::Publish(p))) => {
+            assert_eq!(p.topic_name, "a/b");
+            assert_eq!(p.payload, b"hello");
+        }
+        other => panic!("Failed decode: {:?}", other),
+    }
+    assert_eq!(None, iter.next());
+    assert_eq!(iter.consumed(), 18);
+    assert_eq!(iter.remaining(), &[0b10110000, 2, 0]);
+}
+
+#[test]
+fn test_packet_iter_error() {
+    let data: &[u8] = &[
+        0b11000000, 0b00000000, // Pingreq
+        0b00000000, 0b00000000, // Invalid packet type
+        0b11000000, 0b00000000, // Pingreq
+    ];
+    let mut iter = PacketIter::new(data);
+    assert_eq!(Some(Ok(Packet::Pingreq)), iter.next());
+    assert_eq!(Some(Err(Error::InvalidHeader)), iter.next());
+    assert_eq!(None, iter.next());
+    assert_eq!(iter.remaining(), &data[2..]);
+}
+
+/// Fields that would extend past the packet's remaining length must not be read from the rest of
+/// the buffer (or panic when there is no rest).
+#[test]
+fn truncated_fields() {
+    for data in [
+        // Connect without flags and keepalive
+        vec![0b00010000, 7, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04],
+        // Connect without protocol level
+        vec![0b00010000, 6, 0x00, 0x04, b'M', b'Q', b'T', b'T'],
+        // Connack without return code
+        vec![0b00100000, 1, 0],
+        // Puback without pid
+        vec![0b01000000, 1, 0],
+        // Publish with topic longer than the packet
+        vec![0b00110000, 4, 0x00, 0x04, b'a', b'/'],
+        // Publish qos1 without pid
+        vec![0b00110010, 5, 0x00, 0x03, b'a', b'/', b'b'],
+        // Subscribe without topic qos
+        vec![0b10000010, 5, 0, 10, 0, 1, b'a'],
+        // Suback without pid
+        vec![0b10010000, 1, 0],
+    ] {
+        assert_eq!(Err(Error::TruncatedBody), decode_slice(&data), "{:?}", data);
+        // Same result when the next packet is already in the buffer.
+        let mut padded = data.clone();
+        padded.extend_from_slice(&[0b11000000, 0, 0, 0]);
+        assert_eq!(
+            Err(Error::TruncatedBody),
+            decode_slice(&padded),
+            "{:?}",
+            padded
+        );
+    }
+}
+
+/// Every packet type must use exactly its remaining_len.
+#[test]
+fn trailing_bytes() {
+    for data in [
+        // Connect with trailing garbage
+        vec![
+            0b00010000, 17, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0, 0x00, 0x0a, 0x00, 0x04,
+            b't', b'e', b's', b't', 0xff,
+        ],
+        // Connack
+        vec![0b00100000, 3, 0, 0, 0],
+        // Puback with remaining_len=5
+        vec![0b01000000, 5, 0, 10, 0, 0, 0],
+        vec![0b01010000, 3, 0, 10, 0],
+        vec![0b01100010, 3, 0, 10, 0],
+        vec![0b01110000, 3, 0, 10, 0],
+        vec![0b10110000, 3, 0, 10, 0],
+        // Packets with no body
+        vec![0b11000000, 1, 0],
+        vec![0b11010000, 1, 0],
+        vec![0b11100000, 2, 0, 0],
+    ] {
+        assert_eq!(Err(Error::TrailingBytes), decode_slice(&data), "{:?}", data);
+    }
+}
+
+#[test]
+fn clone_packet_small_output() {
+    let data: &[u8] = &[0b01000000, 0b00000010, 0, 10];
+    let mut out = [0u8; 3];
+    assert_eq!(Err(Error::WriteZero), clone_packet(data, &mut out));
+}
+
+proptest! {
+    /// Decoding arbitrary bytes must return a `Result`, never panic.
+    #[test]
+    fn decode_arbitrary(data in vec(any::<u8>(), 0..300)) {
+        let _ = decode_slice(&data);
+        let _ = PacketIter::new(&data).count();
+    }
+
+    /// Same as above, but with a consistent remaining length so that the packet bodies get parsed.
+    #[test]
+    fn decode_arbitrary_body(header in any::<u8>(), body in vec(any::<u8>(), 0..128)) {
+        let mut data = vec![header, body.len() as u8];
+        data.extend_from_slice(&body);
+        let _ = decode_slice(&data);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_decoder_bytewise() {
+    let data: &[u8] = &[
+        0b01000000, 0b00000010, 0, 10, // Puback
+        0b11000000, 0b00000000, // Pingreq
+        0b00110000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', // Publish
+    ];
+    let mut decoder = Decoder::new(std::vec::Vec::new());
+    let mut decoded = 0;
+    for byte in data {
+        decoder.feed(&[*byte]).unwrap();
+        match decoder.decode() {
+            Ok(None) => (),
+            Ok(Some(Packet::Puback(pid))) if decoded == 0 => assert_eq!(pid.get(), 10),
+            Ok(Some(Packet::Pingreq)) if decoded == 1 => (),
+            Ok(Some(Packet::Publish(p))) if decoded == 2 => assert_eq!(p.payload, b"hello"),
+            other => panic!("Failed decode {}: {:?}", decoded, other),
+        }
+        if decoder.buffered_len() == 0 {
+            decoded += 1;
+        }
+    }
+    assert_eq!(decoded, 3);
+    assert_eq!(Ok(None), decoder.decode());
+    assert!(decoder.into_inner().is_empty());
+}
+
+#[test]
+fn test_decoder_heapless() {
+    let mut decoder = Decoder::new(heapless::Vec::<u8, 6>::new());
+    decoder.feed(&[0b01000000, 0b00000010, 0]).unwrap();
+    assert_eq!(Ok(None), decoder.decode());
+    assert_eq!(
+        Err(Error::WriteZero),
+        decoder.feed(&[10, 0b11000000, 0, 0b11010000])
+    );
+    decoder.feed(&[10, 0b11000000, 0]).unwrap();
+    assert_eq!(
+        Ok(Some(Packet::Puback(Pid::try_from(10).unwrap()))),
+        decoder.decode()
+    );
+    // The Puback's space is reclaimed on the next call.
+    decoder.feed(&[0b11010000, 0, 0b11100000, 0]).unwrap();
+    assert_eq!(Ok(Some(Packet::Pingreq)), decoder.decode());
+    assert_eq!(Ok(Some(Packet::Pingresp)), decoder.decode());
+    assert_eq!(Ok(Some(Packet::Disconnect)), decoder.decode());
+    assert_eq!(Ok(None), decoder.decode());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_decoder_error() {
+    let mut decoder = Decoder::new(std::vec::Vec::new());
+    decoder
+        .feed(&[0b01000000, 0b00000010, 0, 0, 0b11000000, 0])
+        .unwrap();
+    assert_eq!(Err(Error::InvalidPid), decoder.decode());
+    assert_eq!(Ok(Some(Packet::Pingreq)), decoder.decode());
+}
+
+#[test]
+fn test_max_packet_size() {
+    let opts = DecodeOptions::new()
+        .max_packet_size(4)
+        .max_packet_size_for(PacketType::Publish, 12);
+    let puback: &[u8] = &[0b01000000, 0b00000010, 0, 10];
+    let publish: &[u8] = &[
+        0b00110000, 10, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o',
+    ];
+    assert!(matches!(
+        decode_slice_with_options(puback, &opts),
+        Ok(Some((Packet::Puback(_), 4)))
+    ));
+    assert!(matches!(
+        decode_slice_with_options(publish, &opts),
+        Ok(Some((Packet::Publish(_), 12)))
+    ));
+
+    // Fail as soon as the header is available.
+    let opts = DecodeOptions::new().max_packet_size(11);
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        decode_slice_with_options(&publish[..2], &opts)
+    );
+    assert_eq!(Ok(None), decode_slice_with_options(&publish[..1], &opts));
+    let mut iter = PacketIter::with_options(publish, opts);
+    assert_eq!(Some(Err(Error::PacketTooLarge)), iter.next());
+
+    let opts = DecodeOptions::new().max_packet_size_for(PacketType::Puback, 3);
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        decode_slice_with_options(puback, &opts)
+    );
+    assert!(decode_slice_with_options(publish, &opts).is_ok());
+}
+
+#[test]
+fn test_decoder_max_packet_size() {
+    // Remaining length of 256MB, body not received.
+    let huge: &[u8] = &[0b00110000, 0xff, 0xff, 0xff, 0x7f];
+
+    let mut decoder = Decoder::new(heapless::Vec::<u8, 64>::new());
+    decoder.feed(huge).unwrap();
+    assert_eq!(Err(Error::PacketTooLarge), decoder.decode());
+
+    #[cfg(feature = "std")]
+    {
+        let opts = DecodeOptions::new().max_packet_size(1024);
+        let mut decoder = Decoder::with_options(std::vec::Vec::new(), opts);
+        decoder.feed(&huge[..2]).unwrap();
+        assert_eq!(Ok(None), decoder.decode());
+        decoder.feed(&huge[2..]).unwrap();
+        assert_eq!(Err(Error::PacketTooLarge), decoder.decode());
+    }
+}
+
+#[test]
+fn test_decoder_skip_too_large() {
+    // 22-byte Publish that doesn't fit, followed by a Pingreq.
+    let publish: &[u8] = &[
+        0b00110000, 20, 0x00, 0x03, b'a', b'/', b'b', b'h', b'e', b'l', b'l', b'o', b' ', b'w',
+        b'o', b'r', b'l', b'd', b'!', b'!', b'!', b'!',
+    ];
+    let mut decoder = Decoder::new(heapless::Vec::<u8, 8>::new());
+    decoder.feed(&publish[..5]).unwrap();
+    assert_eq!(Err(Error::PacketTooLarge), decoder.decode());
+    assert_eq!(0, decoder.buffered_len());
+    assert_eq!(Ok(None), decoder.decode());
+
+    // The rest of the packet is dropped as it arrives, even if it's bigger than the buffer.
+    decoder.feed(&publish[5..15]).unwrap();
+    assert_eq!(0, decoder.buffered_len());
+    assert_eq!(Ok(None), decoder.decode());
+    let mut tail = publish[15..].to_vec();
+    tail.extend_from_slice(&[0b11000000, 0]);
+    decoder.feed(&tail).unwrap();
+    assert_eq!(Ok(Some(Packet::Pingreq)), decoder.decode());
+    assert_eq!(Ok(None), decoder.decode());
+
+    // Same with a size limit, with the whole packet already buffered.
+    let opts = DecodeOptions::new().max_packet_size(16);
+    let mut decoder = Decoder::with_options(heapless::Vec::<u8, 32>::new(), opts);
+    decoder.feed(publish).unwrap();
+    decoder.feed(&[0b11010000, 0]).unwrap();
+    assert_eq!(Err(Error::PacketTooLarge), decoder.decode());
+    assert_eq!(Ok(Some(Packet::Pingresp)), decoder.decode());
+}
+
+#[test]
+fn test_peek_header() {
+    let data: &[u8] = &[0b00111011, 0x80, 0x01, 0x00, 0x03, b'a'];
+    let expected = FixedHeader {
+        typ: PacketType::Publish,
+        dup: true,
+        qos: QoS::AtLeastOnce,
+        retain: true,
+        remaining_len: 128,
+        header_len: 3,
+    };
+    assert_eq!(Ok(Some(expected)), peek_header(data));
+    assert_eq!(expected.packet_len(), 131);
+    assert_eq!(Ok(Some(expected)), peek_header(&data[..3]));
+    assert_eq!(Ok(None), peek_header(&data[..2]));
+    assert_eq!(Ok(None), peek_header(&[]));
+    assert_eq!(Err(Error::InvalidHeader), peek_header(&[0b00000000, 0]));
+    assert_eq!(
+        Err(Error::InvalidHeader),
+        peek_header(&[0b11000000, 0x80, 0x80, 0x80, 0x80])
+    );
+    assert_eq!(
+        Ok(Some(FixedHeader {
+            typ: PacketType::Pingreq,
+            dup: false,
+            qos: QoS::AtMostOnce,
+            retain: false,
+            remaining_len: 0,
+            header_len: 2,
+        })),
+        peek_header(&[0b11000000, 0])
+    );
+}
+
+#[test]
+fn test_lenient() {
+    let lenient = DecodeOptions::new().lenient(true);
+    let strict = DecodeOptions::new();
+
+    // Reserved flags
+    let data: &[u8] = &[0b01001000, 2, 0, 10];
+    let mut warnings = Warnings::new();
+    assert_eq!(
+        Ok(Some((Packet::Puback(Pid::try_from(10).unwrap()), 4))),
+        decode_slice_with_warnings(data, &lenient, &mut warnings)
+    );
+    assert_eq!(
+        &warnings[..],
+        &[Warning::ReservedFlags {
+            typ: PacketType::Puback,
+            flags: 0b1000
+        }]
+    );
+    assert_eq!(
+        Err(Error::InvalidHeader),
+        decode_slice_with_options(data, &strict)
+    );
+    // Reserved packet types are still an error.
+    assert_eq!(
+        Err(Error::InvalidHeader),
+        decode_slice_with_options(&[0b11110000, 0], &lenient)
+    );
+
+    // Unknown protocol
+    let data: &[u8] = &[
+        0b00010000, 16, 0x00, 0x04, b'M', b'Q', b'T', b'X', 0x07, 0b00000010, 0x00, 0x0a, 0x00,
+        0x04, b't', b'e', b's', b't',
+    ];
+    let mut warnings = Warnings::new();
+    match decode_slice_with_warnings(data, &lenient, &mut warnings) {
+        Ok(Some((Packet::Connect(c), 18))) => {
+            assert_eq!(c.protocol, Protocol::MQTT311);
+            assert_eq!(c.client_id, "test");
+        }
+        other => panic!("Failed decode: {:?}", other),
+    }
+    assert_eq!(
+        &warnings[..],
+        &[Warning::UnknownProtocol {
+            name: b"MQTX",
+            level: 7
+        }]
+    );
+    assert!(matches!(
+        decode_slice_with_options(data, &strict),
+        Err(Error::InvalidProtocol(_, 7))
+    ));
+
+    // Invalid utf8
+    let data: &[u8] = &[
+        0b00110000, 10, 0x00, 0x03, b'a', 0xc0, b'/', b'h', b'e', b'l', b'l', b'o',
+    ];
+    let mut warnings = Warnings::new();
+    match decode_slice_with_warnings(data, &lenient, &mut warnings) {
+        Ok(Some((Packet::Publish(p), 12))) => {
+            assert_eq!(p.topic_name, "a");
+            assert_eq!(p.payload, b"hello");
+        }
+        other => panic!("Failed decode: {:?}", other),
+    }
+    assert_eq!(
+        &warnings[..],
+        &[Warning::InvalidString {
+            field: Field::TopicName,
+            raw: &[b'a', 0xc0, b'/']
+        }]
+    );
+    assert!(matches!(
+        decode_slice_with_options(data, &strict),
+        Err(Error::InvalidString(Field::TopicName, _))
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_decoder_lenient() {
+    let mut decoder =
+        Decoder::with_options(std::vec::Vec::new(), DecodeOptions::new().lenient(true));
+    decoder.feed(&[0b11000001, 0, 0b11010000, 0]).unwrap();
+    let mut warnings = Warnings::new();
+    assert_eq!(
+        Ok(Some(Packet::Pingreq)),
+        decoder.decode_with_warnings(&mut warnings)
+    );
+    assert_eq!(warnings.len(), 1);
+    let mut warnings = Warnings::new();
+    assert_eq!(
+        Ok(Some(Packet::Pingresp)),
+        decoder.decode_with_warnings(&mut warnings)
+    );
+    assert!(warnings.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_decode_bytes() {
+    let mut buf = BytesMut::from(
+        &[
+            0b00110010, 9, 0, 3, b'a', b'/', b'b', 0, 10, b'h', b'i', // publish
+            0b01000000, 2, 0, 10, // puback
+            0b00110000, 5, 0, 1, b'c', // publish, incomplete
+        ][..],
+    );
+    let start = buf.as_ptr() as usize;
+    let publish = match decode_bytes(&mut buf) {
+        Ok(Some(BytesPacket::Publish(p))) => p,
+        other => panic!("Failed decode: {:?}", other),
+    };
+    assert_eq!(publish.topic_name(), "a/b");
+    assert_eq!(&publish.payload[..], b"hi");
+    assert_eq!(
+        publish.qospid,
+        QosPid::AtLeastOnce(Pid::try_from(10).unwrap())
+    );
+    // Fields point into the original buffer.
+    assert_eq!(publish.topic_name_bytes().as_ptr() as usize, start + 4);
+    assert_eq!(publish.payload.as_ptr() as usize, start + 9);
+
+    let puback = decode_bytes(&mut buf).unwrap().unwrap();
+    assert_eq!(
+        puback.as_borrowed(),
+        Packet::Puback(Pid::try_from(10).unwrap())
+    );
+    assert_eq!(Ok(None), decode_bytes(&mut buf));
+    assert_eq!(buf.len(), 5);
+
+    // A complete but invalid packet is consumed.
+    buf.clear();
+    buf.extend_from_slice(&[0b00110000, 3, 0, 1, 0xFF, 0b11000000, 0]);
+    assert!(matches!(
+        decode_bytes(&mut buf),
+        Err(Error::InvalidString(Field::TopicName, _))
+    ));
+    assert_eq!(Ok(Some(BytesPacket::Pingreq)), decode_bytes(&mut buf));
+
+    // Oversized packets are rejected from their fixed header, and are left in the buffer.
+    let opts = DecodeOptions::new().max_packet_size(8).lenient(true);
+    buf.extend_from_slice(&[0b00110000, 20, 0, 1]);
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        decode_bytes_with_options(&mut buf, &opts)
+    );
+    assert_eq!(buf.len(), 4);
+    buf.clear();
+    buf.extend_from_slice(&[0b11000001, 0]);
+    assert_eq!(Err(Error::InvalidHeader), decode_bytes(&mut buf));
+    assert_eq!(
+        Ok(Some(BytesPacket::Pingreq)),
+        decode_bytes_with_options(&mut buf, &opts)
+    );
+
+    let new_publish = |topic: std::vec::Vec<u8>| {
+        let topic = bytes::Bytes::from(topic);
+        BytesPublish::new(false, QosPid::AtMostOnce, false, topic, bytes::Bytes::new())
+    };
+    assert!(matches!(
+        new_publish(std::vec![0xFF]),
+        Err(Error::InvalidString(Field::TopicName, _))
+    ));
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicName)),
+        new_publish(b"a\0b".to_vec())
+    );
+    assert_eq!(
+        Err(Error::FieldTooLong(Field::TopicName)),
+        new_publish(std::vec![b'a'; 65536])
+    );
+    assert!(new_publish(b"a/b".to_vec()).is_ok());
+}
+
+#[test]
+fn test_subscribe_ref() {
+    let opts = DecodeOptions::new();
+    // More topics than fit in a `no_std` Subscribe.
+    let mut buf = std::vec![0b10000010, 0, 0, 10];
+    for i in 0..7u8 {
+        buf.extend_from_slice(&[0, 2, b't', b'0' + i, i % 3]);
+    }
+    buf[1] = (buf.len() - 2) as u8;
+    let (subscribe, len) = SubscribeRef::from_slice(&buf, &opts).unwrap().unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(subscribe.pid, Pid::try_from(10).unwrap());
+    let topics: std::vec::Vec<_> = subscribe.topics().collect::<Result<_, _>>().unwrap();
+    assert_eq!(topics.len(), 7);
+    assert_eq!(topics[6].topic_path, "t6");
+    assert_eq!(topics[6].qos, QoS::AtMostOnce);
+    assert_eq!(Ok(None), SubscribeRef::from_slice(&buf[..len - 1], &opts));
+
+    // Invalid items are only reported when reached, and end the iteration.
+    let buf = [0b10000010, 11, 0, 10, 0, 1, b'a', 1, 0, 1, 0xFF, 1, 0];
+    let (subscribe, _) = SubscribeRef::from_slice(&buf, &opts).unwrap().unwrap();
+    let mut topics = subscribe.topics();
+    assert!(matches!(topics.next(), Some(Ok(_))));
+    assert!(matches!(
+        topics.next(),
+        Some(Err(Error::InvalidString(Field::TopicFilter, _)))
+    ));
+    assert_eq!(None, topics.next());
+    let buf = [0b10000010, 6, 0, 10, 0, 1, b'a', 3];
+    let (subscribe, _) = SubscribeRef::from_slice(&buf, &opts).unwrap().unwrap();
+    assert_eq!(
+        std::vec![Err(Error::InvalidQos(3))],
+        subscribe.topics().collect::<std::vec::Vec<_>>()
+    );
+
+    // Wrong packet type, or no topics at all.
+    assert_eq!(
+        Err(Error::InvalidHeader),
+        SubscribeRef::from_slice(&[0b10100010, 5, 0, 10, 0, 1, b'a'], &opts)
+    );
+    assert_eq!(
+        Err(Error::EmptyPayload),
+        SubscribeRef::from_slice(&[0b10000010, 2, 0, 10], &opts)
+    );
+
+    let buf = [0b10100010, 9, 0, 10, 0, 1, b'a', 0, 2, b'b', b'c'];
+    let (unsubscribe, _) = UnsubscribeRef::from_slice(&buf, &opts).unwrap().unwrap();
+    let topics: Result<std::vec::Vec<_>, _> = unsubscribe.topics().collect();
+    assert_eq!(Ok(std::vec!["a", "bc"]), topics);
+
+    let buf = [0b10010000, 5, 0, 10, 0, 0x80, 2];
+    let (suback, _) = SubackRef::from_slice(&buf, &opts).unwrap().unwrap();
+    let codes: Result<std::vec::Vec<_>, _> = suback.return_codes().collect();
+    assert_eq!(
+        Ok(std::vec![
+            SubscribeReturnCodes::Success(QoS::AtMostOnce),
+            SubscribeReturnCodes::Failure,
+            SubscribeReturnCodes::Success(QoS::ExactlyOnce),
+        ]),
+        codes
+    );
+
+    // Options apply like for other packets.
+    let buf = [0b10000010, 11, 0, 10, 0, 1, b'a', 1, 0, 1, 0xFF, 1, 0];
+    let small = DecodeOptions::new().max_packet_size_for(PacketType::Subscribe, 12);
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        SubscribeRef::from_slice(&buf[..2], &small)
+    );
+    let lenient = DecodeOptions::new().lenient(true);
+    let (subscribe, _) = SubscribeRef::from_slice(&buf, &lenient).unwrap().unwrap();
+    let mut topics = subscribe.topics().skip(1);
+    assert_eq!(Some(""), topics.next().map(|t| t.unwrap().topic_path));
+    assert_eq!(
+        Err(Error::InvalidHeader),
+        UnsubscribeRef::from_slice(&[0b10100000, 5, 0, 10, 0, 1, b'a'], &opts)
+    );
+    assert!(matches!(
+        UnsubscribeRef::from_slice(&[0b10100000, 5, 0, 10, 0, 1, b'a'], &lenient),
+        Ok(Some((_, 7)))
+    ));
+}
+
+#[test]
+fn test_custom_capacity() {
+    let opts = DecodeOptions::new().capacity::<8, 64>();
+    let subscribe = SubscribeBuilder::<8, 64>::new(Pid::new())
+        .topic("a", QoS::AtMostOnce)
+        .topic("b/#", QoS::ExactlyOnce)
+        .build()
+        .unwrap();
+    let packet: Packet<8, 64> = subscribe.into();
+    let mut buf = [0u8; 64];
+    let len = packet.encode_slice(&mut buf).unwrap();
+
+    assert_eq!(
+        Ok(Some((packet.clone(), len))),
+        decode_slice_with_options(&buf[..len], &opts)
+    );
+    let mut iter = PacketIter::with_options(&buf[..len], opts);
+    assert_eq!(Some(Ok(packet.clone())), iter.next());
+    let mut decoder = Decoder::with_options(heapless::Vec::<u8, 64>::new(), opts);
+    decoder.feed(&buf[..len]).unwrap();
+    assert_eq!(Ok(Some(packet)), decoder.decode());
+}
+
+#[cfg(not(feature = "std"))]
+#[test]
+fn test_capacity_limits() {
+    let subscribe = |topic: &[u8]| {
+        let mut data = heapless::Vec::<u8, 64>::new();
+        let len = 2 + 5 * 4 + 2 + topic.len() + 1;
+        data.extend_from_slice(&[0b10000010, len as u8, 0, 10])
+            .unwrap();
+        for name in b"abcde" {
+            data.extend_from_slice(&[0, 1, *name, 0]).unwrap();
+        }
+        data.extend_from_slice(&[0, topic.len() as u8]).unwrap();
+        data.extend_from_slice(topic).unwrap();
+        data.push(0).unwrap();
+        data
+    };
+
+    // 6 topics don't fit in the default capacity of 5
+    let data = subscribe(b"f");
+    let opts = DecodeOptions::new().capacity::<8, _>();
+    let res: Option<(Packet<8>, usize)> = decode_slice_with_options(&data, &opts).unwrap();
+    match res {
+        Some((Packet::Subscribe(s), len)) => {
+            assert_eq!(len, data.len());
+            assert_eq!(s.topics.len(), 6);
+            assert_eq!(s.topics[5].topic_path, "f");
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert_eq!(Err(Error::InvalidLength), decode_slice(&data));
+    let opts = DecodeOptions::new().capacity::<5, 256>();
+    assert_eq!(
+        Err(Error::InvalidLength),
+        decode_slice_with_options(&data, &opts)
+    );
+
+    // Topics must fit in `L` bytes
+    let data = subscribe(b"f/g/h");
+    let opts = DecodeOptions::new().capacity::<8, 4>();
+    assert_eq!(
+        Err(Error::InvalidLength),
+        decode_slice_with_options(&data, &opts)
+    );
+    let opts = DecodeOptions::new().capacity::<8, 5>();
+    assert!(decode_slice_with_options(&data, &opts).is_ok());
+}
@@ -1,10 +1,184 @@
-use crate::{Error, Packet};
+use crate::{
+    utils::{check_bytes, check_str, Field},
+    ConnectReturnCode, Error, Packet, Pid,
+};
+#[cfg(feature = "std")]
+use bytes::{BufMut, BytesMut};
+use core::ops::Range;
 
-/// Encode a [Packet] enum into a [BufMut] buffer.
+/// Encode a [Packet] enum, appending it to a [BytesMut] buffer.
+///
+/// The buffer reserves the exact size of the packet once, then the packet is written straight
+/// into it, big fields like the [Publish] payload being copied only once. The number of bytes
+/// written is returned. An invalid packet returns an error before anything is written.
 ///
 /// ```
 /// # use mqttrs::*;
 /// # use bytes::*;
+/// let packet: Packet = Publish {
+///    dup: false,
+///    qospid: QosPid::AtMostOnce,
+///    retain: false,
+///    topic_name: "test",
+///    payload: b"hello",
+/// }.into();
+///
+/// let mut buf = BytesMut::new();
+/// let len = encode(&packet, &mut buf).expect("failed encoding");
+/// assert_eq!(len, 13);
+/// assert_eq!(&buf[..], &[0b00110000, 11,
+///                        0, 4, b't', b'e', b's', b't',
+///                        b'h', b'e', b'l', b'l', b'o']);
+/// ```
+///
+/// Only available with the `std` feature, use [encode_slice()] otherwise.
+///
+/// [Packet]: ../enum.Packet.html
+/// [Publish]: struct.Publish.html
+/// [BytesMut]: https://docs.rs/bytes/1.0.0/bytes/struct.BytesMut.html
+/// [encode_slice()]: fn.encode_slice.html
+#[cfg(feature = "std")]
+pub fn encode<const N: usize, const L: usize>(
+    packet: &Packet<N, L>,
+    buf: &mut BytesMut,
+) -> Result<usize, Error> {
+    let len = packet.encoded_len();
+    if len > packet_len(MAX_REMAINING_LEN) {
+        return Err(Error::PacketTooLarge);
+    }
+    buf.reserve(len);
+    packet_to_sink(packet, &mut BufSink(buf))
+}
+
+/// Write a [Packet] enum to a [std::io::Write], returning the number of bytes written.
+///
+/// Unlike [encode()], this doesn't need a buffer as big as the packet: the fixed header and small
+/// fields are encoded on the stack, and big fields like the [Publish] payload are written straight
+/// from the packet. The packet is validated before anything is written, so an invalid packet
+/// doesn't leave a partial packet in the writer.
+///
+/// Each packet needs a few `write_all()` calls, so unbuffered writers like `TcpStream` should be
+/// wrapped in a `BufWriter`. The writer isn't flushed.
+///
+/// ```
+/// # use mqttrs::*;
+/// let payload = vec![42u8; 1_000_000];
+/// let packet = Publish {
+///    dup: false,
+///    qospid: QosPid::AtMostOnce,
+///    retain: false,
+///    topic_name: "test",
+///    payload: &payload,
+/// }.into();
+///
+/// let mut out = Vec::new();
+/// let len = write_packet(&packet, &mut out).expect("failed writing");
+/// assert_eq!(len, 1_000_010);
+/// assert_eq!(Ok(Some(packet)), decode_slice(&out));
+/// ```
+///
+/// Only available with the `std` feature, see [write_packet_embedded()] otherwise.
+///
+/// [Packet]: ../enum.Packet.html
+/// [Publish]: struct.Publish.html
+/// [std::io::Write]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [encode()]: fn.encode.html
+/// [write_packet_embedded()]: fn.write_packet_embedded.html
+#[cfg(feature = "std")]
+pub fn write_packet<const N: usize, const L: usize>(
+    packet: &Packet<N, L>,
+    writer: impl std::io::Write,
+) -> Result<usize, Error> {
+    packet_to_sink(packet, &mut IoSink(writer))
+}
+
+/// Write a [Packet] enum to an [embedded_io::Write], returning the number of bytes written.
+///
+/// This is the same as [write_packet()], for the `embedded-io` traits. Writer errors are returned
+/// as `Error::WriteZero` or `Error::EmbeddedIoError`.
+///
+/// Only available with the `embedded-io` feature.
+///
+/// [Packet]: ../enum.Packet.html
+/// [embedded_io::Write]: https://docs.rs/embedded-io/0.6/embedded_io/trait.Write.html
+/// [write_packet()]: fn.write_packet.html
+#[cfg(feature = "embedded-io")]
+pub fn write_packet_embedded<const N: usize, const L: usize>(
+    packet: &Packet<N, L>,
+    writer: impl embedded_io::Write,
+) -> Result<usize, Error> {
+    packet_to_sink(packet, &mut EmbeddedSink(writer))
+}
+
+/// Destination of a packet encoded in pieces.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub(crate) trait Sink {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error>;
+}
+
+#[cfg(feature = "std")]
+struct IoSink<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Sink for IoSink<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        Ok(self.0.write_all(bytes)?)
+    }
+}
+
+/// Sink for [`encode()`], which reserves the space beforehand.
+///
+/// [`encode()`]: fn.encode.html
+#[cfg(feature = "std")]
+struct BufSink<'b>(&'b mut BytesMut);
+
+#[cfg(feature = "std")]
+impl Sink for BufSink<'_> {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.0.put_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+struct EmbeddedSink<W>(W);
+
+#[cfg(feature = "embedded-io")]
+impl<W: embedded_io::Write> Sink for EmbeddedSink<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        use embedded_io::{Error as _, ErrorKind};
+        self.0.write_all(bytes).map_err(|e| match e.kind() {
+            ErrorKind::WriteZero => Error::WriteZero,
+            kind => Error::EmbeddedIoError(kind),
+        })
+    }
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn packet_to_sink<const N: usize, const L: usize>(
+    packet: &Packet<N, L>,
+    sink: &mut impl Sink,
+) -> Result<usize, Error> {
+    match packet {
+        Packet::Connect(connect) => connect.to_sink(sink),
+        Packet::Publish(publish) => publish.to_sink(sink),
+        Packet::Subscribe(subscribe) => subscribe.to_sink(sink),
+        Packet::Suback(suback) => suback.to_sink(sink),
+        Packet::Unsubscribe(unsub) => unsub.to_sink(sink),
+        _ => {
+            // Remaining packets are at most 4 bytes long
+            let mut buf = [0u8; 4];
+            let len = packet.encode_slice(&mut buf)?;
+            sink.write_all(&buf[..len])?;
+            Ok(len)
+        }
+    }
+}
+
+/// Encode a [Packet] enum into a slice, returning the number of bytes written.
+///
+/// ```
+/// # use mqttrs::*;
 /// // Instantiate a `Packet` to encode.
 /// let packet = Publish {
 ///    dup: false,
@@ -25,160 +199,418 @@ use crate::{Error, Packet};
 /// ```
 ///
 /// [Packet]: ../enum.Packet.html
-/// [BufMut]: https://docs.rs/bytes/1.0.0/bytes/trait.BufMut.html
-// #[cfg(feature = "std")]
-// pub fn encode_slice(packet: &Packet, buf: impl BufMut) -> Result<usize, Error> {
-//     let mut offset = 0;
-//     encode_slice(packet, buf.bytes_mut(), &mut offset)
-// }
-
 pub fn encode_slice(packet: &Packet, buf: &mut [u8]) -> Result<usize, Error> {
-    let mut offset = 0;
+    packet.encode_slice(buf)
+}
 
-    match packet {
-        Packet::Connect(connect) => connect.to_buffer(buf, &mut offset),
-        Packet::Connack(connack) => connack.to_buffer(buf, &mut offset),
-        Packet::Publish(publish) => publish.to_buffer(buf, &mut offset),
-        Packet::Puback(pid) => {
-            check_remaining(buf, &mut offset, 4)?;
-            let header: u8 = 0b01000000;
-            let length: u8 = 2;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            pid.to_buffer(buf, &mut offset)?;
-            Ok(4)
-        }
-        Packet::Pubrec(pid) => {
-            check_remaining(buf, &mut offset, 4)?;
-            let header: u8 = 0b01010000;
-            let length: u8 = 2;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            pid.to_buffer(buf, &mut offset)?;
-            Ok(4)
-        }
-        Packet::Pubrel(pid) => {
-            check_remaining(buf, &mut offset, 4)?;
-            let header: u8 = 0b01100010;
-            let length: u8 = 2;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            pid.to_buffer(buf, &mut offset)?;
-            Ok(4)
-        }
-        Packet::Pubcomp(pid) => {
-            check_remaining(buf, &mut offset, 4)?;
-            let header: u8 = 0b01110000;
-            let length: u8 = 2;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            pid.to_buffer(buf, &mut offset)?;
-            Ok(4)
-        }
-        Packet::Subscribe(subscribe) => subscribe.to_buffer(buf, &mut offset),
-        Packet::Suback(suback) => suback.to_buffer(buf, &mut offset),
-        Packet::Unsubscribe(unsub) => unsub.to_buffer(buf, &mut offset),
-        Packet::Unsuback(pid) => {
-            check_remaining(buf, &mut offset, 4)?;
-            let header: u8 = 0b10110000;
-            let length: u8 = 2;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            pid.to_buffer(buf, &mut offset)?;
-            Ok(4)
-        }
-        Packet::Pingreq => {
-            check_remaining(buf, &mut offset, 2)?;
-            let header: u8 = 0b11000000;
-            let length: u8 = 0;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            Ok(2)
+impl<'a, const N: usize, const L: usize> Packet<'a, N, L> {
+    /// Same as [`encode_slice()`], for packets of any capacity.
+    ///
+    /// [`encode_slice()`]: fn.encode_slice.html
+    pub fn encode_slice(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut offset = 0;
+
+        match self {
+            Packet::Connect(connect) => connect.to_buffer(buf, &mut offset),
+            Packet::Connack(connack) => connack.to_buffer(buf, &mut offset),
+            Packet::Publish(publish) => publish.to_buffer(buf, &mut offset),
+            Packet::Puback(pid) => write_fixed(buf, &encode_puback(*pid)),
+            Packet::Pubrec(pid) => write_fixed(buf, &encode_pubrec(*pid)),
+            Packet::Pubrel(pid) => write_fixed(buf, &encode_pubrel(*pid)),
+            Packet::Pubcomp(pid) => write_fixed(buf, &encode_pubcomp(*pid)),
+            Packet::Subscribe(subscribe) => subscribe.to_buffer(buf, &mut offset),
+            Packet::Suback(suback) => suback.to_buffer(buf, &mut offset),
+            Packet::Unsubscribe(unsub) => unsub.to_buffer(buf, &mut offset),
+            Packet::Unsuback(pid) => write_fixed(buf, &encode_unsuback(*pid)),
+            Packet::Pingreq => write_fixed(buf, &encode_pingreq()),
+            Packet::Pingresp => write_fixed(buf, &encode_pingresp()),
+            Packet::Disconnect => write_fixed(buf, &encode_disconnect()),
         }
-        Packet::Pingresp => {
-            check_remaining(buf, &mut offset, 2)?;
-            let header: u8 = 0b11010000;
-            let length: u8 = 0;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            Ok(2)
+    }
+}
+
+/// Write a packet produced by one of the const encoders.
+fn write_fixed(buf: &mut [u8], bytes: &[u8]) -> Result<usize, Error> {
+    let mut offset = 0;
+    check_remaining(buf, &mut offset, bytes.len())?;
+    write_slice(buf, &mut offset, bytes)?;
+    Ok(bytes.len())
+}
+
+/// Encode a `Pingreq` packet at compile time.
+///
+/// Packets that don't carry variable data always encode to the same bytes. These `const fn`
+/// encoders let you compute them once, for example to store them in flash as a `static`, and skip
+/// [`encode_slice()`] entirely:
+///
+/// ```
+/// # use mqttrs::*;
+/// static PINGREQ: [u8; 2] = encode_pingreq();
+///
+/// let mut buf = [0u8; 2];
+/// encode_slice(&Packet::Pingreq, &mut buf).unwrap();
+/// assert_eq!(PINGREQ, buf);
+/// ```
+///
+/// [`encode_slice()`]: fn.encode_slice.html
+pub const fn encode_pingreq() -> [u8; 2] {
+    [0b11000000, 0]
+}
+
+/// Encode a `Pingresp` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_pingresp() -> [u8; 2] {
+    [0b11010000, 0]
+}
+
+/// Encode a `Disconnect` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_disconnect() -> [u8; 2] {
+    [0b11100000, 0]
+}
+
+/// Encode a `Puback` packet at compile time. See [`encode_pingreq()`].
+///
+/// ```
+/// # use mqttrs::*;
+/// # use core::num::NonZeroU16;
+/// const PID: Pid = Pid::from_nonzero(match NonZeroU16::new(10) {
+///     Some(n) => n,
+///     None => panic!(),
+/// });
+/// static PUBACK: [u8; 4] = encode_puback(PID);
+///
+/// let mut buf = [0u8; 4];
+/// encode_slice(&Packet::Puback(PID), &mut buf).unwrap();
+/// assert_eq!(PUBACK, buf);
+/// ```
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_puback(pid: Pid) -> [u8; 4] {
+    pid_packet(0b01000000, pid)
+}
+
+/// Encode a `Pubrec` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_pubrec(pid: Pid) -> [u8; 4] {
+    pid_packet(0b01010000, pid)
+}
+
+/// Encode a `Pubrel` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_pubrel(pid: Pid) -> [u8; 4] {
+    pid_packet(0b01100010, pid)
+}
+
+/// Encode a `Pubcomp` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_pubcomp(pid: Pid) -> [u8; 4] {
+    pid_packet(0b01110000, pid)
+}
+
+/// Encode an `Unsuback` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_unsuback(pid: Pid) -> [u8; 4] {
+    pid_packet(0b10110000, pid)
+}
+
+/// Encode a `Connack` packet at compile time. See [`encode_pingreq()`].
+///
+/// [`encode_pingreq()`]: fn.encode_pingreq.html
+pub const fn encode_connack(session_present: bool, code: ConnectReturnCode) -> [u8; 4] {
+    [0b00100000, 2, session_present as u8, code.to_u8()]
+}
+
+/// Encode a packet whose body is just a pid.
+const fn pid_packet(header: u8, pid: Pid) -> [u8; 4] {
+    let [msb, lsb] = pid.get().to_be_bytes();
+    [header, 2, msb, lsb]
+}
+
+/// Encode packets one after the other into a slice, keeping track of where each one is.
+///
+/// A packet that fails to encode, typically with `Error::WriteZero` because it doesn't fit, leaves
+/// the writer unchanged. The packets written so far can then be sent with [`written()`], and the
+/// writer reused after a [`clear()`].
+///
+/// ```
+/// # use mqttrs::*;
+/// # use core::convert::TryFrom;
+/// let mut buf = [0u8; 10];
+/// let mut writer = PacketWriter::new(&mut buf);
+/// let puback = |pid| -> Packet { Packet::Puback(Pid::try_from(pid).unwrap()) };
+/// assert_eq!(Ok(0..4), writer.encode(&puback(1)));
+/// assert_eq!(Ok(4..8), writer.encode(&puback(2)));
+/// assert_eq!(Err(Error::WriteZero), writer.encode(&puback(3)));
+/// assert_eq!(writer.written(), &[0b01000000, 2, 0, 1, 0b01000000, 2, 0, 2]);
+/// ```
+///
+/// [`written()`]: struct.PacketWriter.html#method.written
+/// [`clear()`]: struct.PacketWriter.html#method.clear
+#[derive(Debug)]
+pub struct PacketWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> PacketWriter<'b> {
+    pub fn new(buf: &'b mut [u8]) -> Self {
+        PacketWriter { buf, len: 0 }
+    }
+
+    /// Append `packet` to the buffer, returning its position in the buffer.
+    pub fn encode<const N: usize, const L: usize>(
+        &mut self,
+        packet: &Packet<N, L>,
+    ) -> Result<Range<usize>, Error> {
+        let start = self.len;
+        let len = packet.encode_slice(&mut self.buf[start..])?;
+        self.len += len;
+        Ok(start..self.len)
+    }
+
+    /// The bytes of the packets encoded so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes still available in the buffer.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Forget the packets written so far, to reuse the whole buffer.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// Encode `packets` one after the other into `buf`, yielding the position of each packet.
+///
+/// Iteration stops after yielding the first error, typically `Error::WriteZero` for the first
+/// packet that doesn't fit. The bytes of the packets yielded so far are valid. See
+/// [`PacketWriter`] for more control.
+///
+/// ```
+/// # use mqttrs::*;
+/// # use core::convert::TryFrom;
+/// let acks: Vec<Packet> = (1..=3).map(|i| Packet::Puback(Pid::try_from(i).unwrap())).collect();
+/// let mut buf = [0u8; 10];
+/// let ranges: Vec<_> = encode_many(&acks, &mut buf).collect();
+/// assert_eq!(ranges, [Ok(0..4), Ok(4..8), Err(Error::WriteZero)]);
+/// assert_eq!(Ok(Some(acks[1].clone())), decode_slice(&buf[4..8]));
+/// ```
+///
+/// [`PacketWriter`]: struct.PacketWriter.html
+pub fn encode_many<'b, 'p, 'a: 'p, I, const N: usize, const L: usize>(
+    packets: I,
+    buf: &'b mut [u8],
+) -> EncodeMany<'b, I::IntoIter>
+where
+    I: IntoIterator<Item = &'p Packet<'a, N, L>>,
+{
+    EncodeMany {
+        packets: packets.into_iter(),
+        writer: PacketWriter::new(buf),
+        done: false,
+    }
+}
+
+/// Iterator returned by [`encode_many()`].
+///
+/// [`encode_many()`]: fn.encode_many.html
+#[derive(Debug)]
+pub struct EncodeMany<'b, I> {
+    packets: I,
+    writer: PacketWriter<'b>,
+    done: bool,
+}
+
+impl<'b, 'p, 'a: 'p, I, const N: usize, const L: usize> Iterator for EncodeMany<'b, I>
+where
+    I: Iterator<Item = &'p Packet<'a, N, L>>,
+{
+    type Item = Result<Range<usize>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
         }
-        Packet::Disconnect => {
-            check_remaining(buf, &mut offset, 2)?;
-            let header: u8 = 0b11100000;
-            let length: u8 = 0;
-            write_u8(buf, &mut offset, header)?;
-            write_u8(buf, &mut offset, length)?;
-            Ok(2)
+        match self.packets.next() {
+            Some(packet) => {
+                let res = self.writer.encode(packet);
+                self.done = res.is_err();
+                Some(res)
+            }
+            None => {
+                self.done = true;
+                None
+            }
         }
     }
 }
 
+impl<'b, 'p, 'a: 'p, I, const N: usize, const L: usize> core::iter::FusedIterator
+    for EncodeMany<'b, I>
+where
+    I: Iterator<Item = &'p Packet<'a, N, L>>,
+{
+}
+
 /// Check wether buffer has `len` bytes of write capacity left. Use this to return a clean
 /// Result::Err instead of panicking.
 pub(crate) fn check_remaining(buf: &mut [u8], offset: &mut usize, len: usize) -> Result<(), Error> {
-    if buf[*offset..].len() < len {
+    if buf.len().saturating_sub(*offset) < len {
         Err(Error::WriteZero)
     } else {
         Ok(())
     }
 }
 
+/// Biggest remaining length that can be encoded.
+pub(crate) const MAX_REMAINING_LEN: usize = 268435455;
+
+/// Number of bytes used to encode the remaining length `len`.
+///
 /// http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718023
-pub(crate) fn write_length(buf: &mut [u8], offset: &mut usize, len: usize) -> Result<usize, Error> {
-    let write_len = match len {
-        0..=127 => {
-            check_remaining(buf, offset, len + 1)?;
-            len + 1
-        }
-        128..=16383 => {
-            check_remaining(buf, offset, len + 2)?;
-            len + 2
-        }
-        16384..=2097151 => {
-            check_remaining(buf, offset, len + 3)?;
-            len + 3
-        }
-        2097152..=268435455 => {
-            check_remaining(buf, offset, len + 4)?;
-            len + 4
-        }
-        _ => return Err(Error::InvalidLength),
-    };
+pub(crate) fn length_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16383 => 2,
+        16384..=2097151 => 3,
+        _ => 4,
+    }
+}
+
+/// Size of a packet with a `remaining_len` body, fixed header included.
+pub(crate) fn packet_len(remaining_len: usize) -> usize {
+    1 + length_size(remaining_len) + remaining_len
+}
+
+/// Check that `buf` can hold a whole packet with a `len` body, and write its fixed header.
+/// Returns the size of the whole packet.
+///
+/// This is the only capacity check needed by packet encoders, the body can then be written
+/// without further checks.
+pub(crate) fn start_packet(
+    buf: &mut [u8],
+    offset: &mut usize,
+    header: u8,
+    len: usize,
+) -> Result<usize, Error> {
+    if len > MAX_REMAINING_LEN {
+        return Err(Error::PacketTooLarge);
+    }
+    let write_len = packet_len(len);
+    check_remaining(buf, offset, write_len)?;
+    write_u8(buf, offset, header)?;
+    encode_length(buf, offset, len)?;
+    Ok(write_len)
+}
+
+/// Write the fixed header of a packet with a `len` body, without checking that `buf` can hold the
+/// body. Returns the size of the whole packet.
+pub(crate) fn write_header(
+    buf: &mut [u8],
+    offset: &mut usize,
+    header: u8,
+    len: usize,
+) -> Result<usize, Error> {
+    if len > MAX_REMAINING_LEN {
+        return Err(Error::PacketTooLarge);
+    }
+    check_remaining(buf, offset, 1 + length_size(len))?;
+    write_u8(buf, offset, header)?;
+    encode_length(buf, offset, len)?;
+    Ok(packet_len(len))
+}
+
+/// http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718023
+fn encode_length(buf: &mut [u8], offset: &mut usize, len: usize) -> Result<(), Error> {
     let mut done = false;
     let mut x = len;
     while !done {
         let mut byte = (x % 128) as u8;
-        x = x / 128;
+        x /= 128;
         if x > 0 {
-            byte = byte | 128;
+            byte |= 128;
         }
         write_u8(buf, offset, byte)?;
-        done = x <= 0;
+        done = x == 0;
     }
-    Ok(write_len)
+    Ok(())
 }
 
+/// Write a single byte. Packet encoders check the capacity once with [`start_packet()`], this
+/// still returns `Error::WriteZero` instead of panicking if that check was too optimistic.
+///
+/// [`start_packet()`]: fn.start_packet.html
 pub(crate) fn write_u8(buf: &mut [u8], offset: &mut usize, val: u8) -> Result<(), Error> {
-    buf[*offset] = val;
+    *buf.get_mut(*offset).ok_or(Error::WriteZero)? = val;
     *offset += 1;
     Ok(())
 }
 
 pub(crate) fn write_u16(buf: &mut [u8], offset: &mut usize, val: u16) -> Result<(), Error> {
-    write_u8(buf, offset, (val >> 8) as u8)?;
-    write_u8(buf, offset, (val & 0xFF) as u8)
+    write_slice(buf, offset, &val.to_be_bytes())
+}
+
+/// Write `bytes` as-is, without a length prefix. Returns `Error::WriteZero` like [`write_u8()`].
+///
+/// [`write_u8()`]: fn.write_u8.html
+pub(crate) fn write_slice(buf: &mut [u8], offset: &mut usize, bytes: &[u8]) -> Result<(), Error> {
+    let end = *offset + bytes.len();
+    buf.get_mut(*offset..end)
+        .ok_or(Error::WriteZero)?
+        .copy_from_slice(bytes);
+    *offset = end;
+    Ok(())
 }
 
-pub(crate) fn write_bytes(buf: &mut [u8], offset: &mut usize, bytes: &[u8]) -> Result<(), Error> {
+pub(crate) fn write_bytes(
+    buf: &mut [u8],
+    offset: &mut usize,
+    bytes: &[u8],
+    field: Field,
+) -> Result<(), Error> {
+    check_bytes(bytes, field)?;
     write_u16(buf, offset, bytes.len() as u16)?;
+    write_slice(buf, offset, bytes)
+}
 
-    for &byte in bytes {
-        write_u8(buf, offset, byte)?;
-    }
-    Ok(())
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub(crate) fn sink_bytes(sink: &mut impl Sink, bytes: &[u8], field: Field) -> Result<(), Error> {
+    check_bytes(bytes, field)?;
+    sink.write_all(&(bytes.len() as u16).to_be_bytes())?;
+    sink.write_all(bytes)
+}
+
+pub(crate) fn write_string(
+    buf: &mut [u8],
+    offset: &mut usize,
+    string: &str,
+    field: Field,
+) -> Result<(), Error> {
+    check_str(string, field)?;
+    write_bytes(buf, offset, string.as_bytes(), field)
 }
 
-pub(crate) fn write_string(buf: &mut [u8], offset: &mut usize, string: &str) -> Result<(), Error> {
-    write_bytes(buf, offset, string.as_bytes())
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub(crate) fn sink_string(sink: &mut impl Sink, string: &str, field: Field) -> Result<(), Error> {
+    sink_bytes(sink, string.as_bytes(), field)
 }
@@ -1,3 +1,6 @@
+// `LimitedVec` is a `heapless::Vec` without std, which has no `to_vec()`.
+#![allow(clippy::iter_cloned_collect)]
+
 use crate::*;
 use core::convert::TryFrom;
 use subscribe::{LimitedString, LimitedVec};
@@ -5,27 +8,50 @@ use subscribe::{LimitedString, LimitedVec};
 #[cfg(feature = "std")]
 use bytes::BytesMut;
 
-// macro_rules! assert_decode {
-//     ($res:pat, $pkt:expr) => {
-//         let mut buf = BytesMut::with_capacity(1024);
-//         let written = encode($pkt, &mut buf).unwrap();
-//         assert_eq!(written, buf.len());
-//         match decode_slice(&mut buf) {
-//             Ok(Some($res)) => (),
-//             err => assert!(
-//                 false,
-//                 "Expected: Ok(Some({}))  got: {:?}",
-//                 stringify!($res),
-//                 err
-//             ),
-//         }
-//     };
-// }
+#[cfg(feature = "std")]
+macro_rules! assert_decode {
+    ($res:pat, $pkt:expr) => {
+        let pkt: &Packet = $pkt;
+        let mut buf = BytesMut::with_capacity(1024);
+        let written = encode(pkt, &mut buf).unwrap();
+        assert_eq!(written, buf.len());
+        assert_eq!(written, pkt.encoded_len());
+        match decode_slice(&mut buf) {
+            Ok(Some($res)) => (),
+            err => assert!(
+                false,
+                "Expected: Ok(Some({}))  got: {:?}",
+                stringify!($res),
+                err
+            ),
+        }
+    };
+}
+#[cfg(not(feature = "std"))]
+macro_rules! assert_decode {
+    ($res:pat, $pkt:expr) => {
+        let pkt: &Packet = $pkt;
+        let mut buf = [0u8; 1024];
+        let written = encode_slice(pkt, &mut buf).unwrap();
+        assert_eq!(written, pkt.encoded_len());
+        match decode_slice(&buf[..written]) {
+            Ok(Some($res)) => (),
+            err => assert!(
+                false,
+                "Expected: Ok(Some({}))  got: {:?}",
+                stringify!($res),
+                err
+            ),
+        }
+    };
+}
 macro_rules! assert_decode_slice {
     ($res:pat, $pkt:expr, $written_exp:expr) => {
+        let pkt: &Packet = $pkt;
         let mut slice = [0u8; 512];
-        let written = encode_slice($pkt, &mut slice).unwrap();
+        let written = encode_slice(pkt, &mut slice).unwrap();
         assert_eq!(written, $written_exp);
+        assert_eq!(written, pkt.encoded_len());
         match decode_slice(&slice[..written]) {
             Ok(Some($res)) => (),
             err => assert!(
@@ -50,7 +76,7 @@ fn test_connect() {
         password: None,
     }
     .into();
-    // assert_decode!(Packet::Connect(_), &packet);
+    assert_decode!(Packet::Connect(_), &packet);
     assert_decode_slice!(Packet::Connect(_), &packet, 18);
 }
 
@@ -85,7 +111,7 @@ fn test_connack() {
         code: ConnectReturnCode::Accepted,
     }
     .into();
-    // assert_decode!(Packet::Connack(_), &packet);
+    assert_decode!(Packet::Connack(_), &packet);
     assert_decode_slice!(Packet::Connack(_), &packet, 4);
 }
 
@@ -96,38 +122,38 @@ fn test_publish() {
         qospid: QosPid::from_u8u16(2, 10),
         retain: true,
         topic_name: "asdf",
-        payload: &['h' as u8, 'e' as u8, 'l' as u8, 'l' as u8, 'o' as u8],
+        payload: b"hello",
     }
     .into();
-    // assert_decode!(Packet::Publish(_), &packet);
+    assert_decode!(Packet::Publish(_), &packet);
     assert_decode_slice!(Packet::Publish(_), &packet, 15);
 }
 
 #[test]
 fn test_puback() {
     let packet = Packet::Puback(Pid::try_from(19).unwrap());
-    // assert_decode!(Packet::Puback(_), &packet);
+    assert_decode!(Packet::Puback(_), &packet);
     assert_decode_slice!(Packet::Puback(_), &packet, 4);
 }
 
 #[test]
 fn test_pubrec() {
     let packet = Packet::Pubrec(Pid::try_from(19).unwrap());
-    // assert_decode!(Packet::Pubrec(_), &packet);
+    assert_decode!(Packet::Pubrec(_), &packet);
     assert_decode_slice!(Packet::Pubrec(_), &packet, 4);
 }
 
 #[test]
 fn test_pubrel() {
     let packet = Packet::Pubrel(Pid::try_from(19).unwrap());
-    // assert_decode!(Packet::Pubrel(_), &packet);
+    assert_decode!(Packet::Pubrel(_), &packet);
     assert_decode_slice!(Packet::Pubrel(_), &packet, 4);
 }
 
 #[test]
 fn test_pubcomp() {
     let packet = Packet::Pubcomp(Pid::try_from(19).unwrap());
-    // assert_decode!(Packet::Pubcomp(_), &packet);
+    assert_decode!(Packet::Pubcomp(_), &packet);
     assert_decode_slice!(Packet::Pubcomp(_), &packet, 4);
 }
 
@@ -139,7 +165,7 @@ fn test_subscribe() {
     };
     let topics: LimitedVec<SubscribeTopic> = [stopic].iter().cloned().collect();
     let packet = Subscribe::new(Pid::try_from(345).unwrap(), topics).into();
-    // assert_decode!(Packet::Subscribe(_), &packet);
+    assert_decode!(Packet::Subscribe(_), &packet);
     assert_decode_slice!(Packet::Subscribe(_), &packet, 10);
 }
 
@@ -150,7 +176,7 @@ fn test_suback() {
         .cloned()
         .collect();
     let packet = Suback::new(Pid::try_from(12321).unwrap(), return_codes).into();
-    // assert_decode!(Packet::Suback(_), &packet);
+    assert_decode!(Packet::Suback(_), &packet);
     assert_decode_slice!(Packet::Suback(_), &packet, 5);
 }
 
@@ -159,31 +185,715 @@ fn test_unsubscribe() {
     let topics: LimitedVec<LimitedString> = [LimitedString::from("a/b")].iter().cloned().collect();
 
     let packet = Unsubscribe::new(Pid::try_from(12321).unwrap(), topics).into();
-    // assert_decode!(Packet::Unsubscribe(_), &packet);
+    assert_decode!(Packet::Unsubscribe(_), &packet);
     assert_decode_slice!(Packet::Unsubscribe(_), &packet, 9);
 }
 
 #[test]
 fn test_unsuback() {
     let packet = Packet::Unsuback(Pid::try_from(19).unwrap());
-    // assert_decode!(Packet::Unsuback(_), &packet);
+    assert_decode!(Packet::Unsuback(_), &packet);
     assert_decode_slice!(Packet::Unsuback(_), &packet, 4);
 }
 
 #[test]
 fn test_ping_req() {
-    // assert_decode!(Packet::Pingreq, &Packet::Pingreq);
+    assert_decode!(Packet::Pingreq, &Packet::Pingreq);
     assert_decode_slice!(Packet::Pingreq, &Packet::Pingreq, 2);
 }
 
 #[test]
 fn test_ping_resp() {
-    // assert_decode!(Packet::Pingresp, &Packet::Pingresp);
+    assert_decode!(Packet::Pingresp, &Packet::Pingresp);
     assert_decode_slice!(Packet::Pingresp, &Packet::Pingresp, 2);
 }
 
 #[test]
 fn test_disconnect() {
-    // assert_decode!(Packet::Disconnect, &Packet::Disconnect);
+    assert_decode!(Packet::Disconnect, &Packet::Disconnect);
     assert_decode_slice!(Packet::Disconnect, &Packet::Disconnect, 2);
 }
+
+#[test]
+fn test_connect_password_without_username() {
+    let packet: Packet = Connect {
+        protocol: Protocol::MQTT311,
+        keep_alive: 120,
+        client_id: "imvj",
+        clean_session: true,
+        last_will: None,
+        username: None,
+        password: Some(b"secret"),
+    }
+    .into();
+    let mut buffer = [0u8; 100];
+    assert_eq!(
+        Err(Error::PasswordWithoutUsername),
+        encode_slice(&packet, &mut buffer)
+    );
+}
+
+#[test]
+fn test_empty_payload() {
+    let mut buffer = [0u8; 10];
+    let pid = Pid::try_from(10).unwrap();
+    let packets: [Packet; 3] = [
+        Subscribe::new(pid, LimitedVec::new()).into(),
+        Suback::new(pid, LimitedVec::new()).into(),
+        Unsubscribe::new(pid, LimitedVec::new()).into(),
+    ];
+    for packet in &packets {
+        assert_eq!(Err(Error::EmptyPayload), encode_slice(packet, &mut buffer));
+    }
+}
+
+#[test]
+fn test_field_too_long() {
+    let long = "a".repeat(65536);
+    let connect = Connect {
+        protocol: Protocol::MQTT311,
+        keep_alive: 60,
+        client_id: "test",
+        clean_session: true,
+        last_will: None,
+        username: Some("user"),
+        password: None,
+    };
+    let will = LastWill {
+        topic: "a/b",
+        message: b"bye",
+        qos: QoS::AtMostOnce,
+        retain: false,
+    };
+    #[cfg_attr(not(feature = "std"), allow(unused_mut))]
+    let mut packets: std::vec::Vec<(Field, Packet)> = std::vec![
+        (
+            Field::ClientId,
+            Connect {
+                client_id: &long,
+                ..connect.clone()
+            }
+            .into(),
+        ),
+        (
+            Field::WillTopic,
+            Connect {
+                last_will: Some(LastWill {
+                    topic: &long,
+                    ..will.clone()
+                }),
+                ..connect.clone()
+            }
+            .into(),
+        ),
+        (
+            Field::WillMessage,
+            Connect {
+                last_will: Some(LastWill {
+                    message: long.as_bytes(),
+                    ..will.clone()
+                }),
+                ..connect.clone()
+            }
+            .into(),
+        ),
+        (
+            Field::Password,
+            Connect {
+                password: Some(long.as_bytes()),
+                ..connect.clone()
+            }
+            .into(),
+        ),
+        (
+            Field::TopicName,
+            Publish {
+                dup: false,
+                qospid: QosPid::AtMostOnce,
+                retain: false,
+                topic_name: &long,
+                payload: b"",
+            }
+            .into(),
+        ),
+    ];
+    // Without std, topic filters can't be longer than `L`.
+    #[cfg(feature = "std")]
+    packets.push((
+        Field::TopicFilter,
+        Unsubscribe::new(Pid::new(), std::vec![long.clone()]).into(),
+    ));
+    let mut buf = std::vec![0u8; 100_000];
+    for (field, packet) in &packets {
+        let err = Err(Error::FieldTooLong(*field));
+        assert_eq!(err, encode_slice(packet, &mut buf));
+        #[cfg(feature = "std")]
+        {
+            assert_eq!(err, encode(packet, &mut BytesMut::new()));
+            let mut out = std::vec::Vec::new();
+            assert_eq!(err, write_packet(packet, &mut out));
+            assert!(out.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_max_remaining_len() {
+    use encoder::{start_packet, write_header, MAX_REMAINING_LEN};
+    let mut buf = [0u8; 8];
+    let mut offset = 0;
+    assert_eq!(
+        Ok(MAX_REMAINING_LEN + 5),
+        write_header(&mut buf, &mut offset, 0b00110000, MAX_REMAINING_LEN)
+    );
+    assert_eq!(&buf[..offset], &[0b00110000, 0xff, 0xff, 0xff, 0x7f]);
+    let mut offset = 0;
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        write_header(&mut buf, &mut offset, 0b00110000, MAX_REMAINING_LEN + 1)
+    );
+    assert_eq!(offset, 0);
+
+    // The size limit is checked before the buffer space.
+    assert_eq!(
+        Err(Error::WriteZero),
+        start_packet(&mut buf, &mut offset, 0b00110000, MAX_REMAINING_LEN)
+    );
+    assert_eq!(
+        Err(Error::PacketTooLarge),
+        start_packet(&mut buf, &mut offset, 0b00110000, MAX_REMAINING_LEN + 1)
+    );
+    assert_eq!(offset, 0);
+}
+
+#[test]
+fn test_write_past_end() {
+    use encoder::{write_slice, write_u8};
+    let mut buf = [0u8; 2];
+    let mut offset = 1;
+    assert_eq!(
+        Err(Error::WriteZero),
+        write_slice(&mut buf, &mut offset, &[1, 2])
+    );
+    assert_eq!(offset, 1);
+    assert_eq!(Ok(()), write_u8(&mut buf, &mut offset, 1));
+    assert_eq!(Err(Error::WriteZero), write_u8(&mut buf, &mut offset, 2));
+    assert_eq!(buf, [0, 1]);
+}
+
+#[test]
+fn test_null_char() {
+    let mut buffer = [0u8; 100];
+    let packet: Packet = Publish {
+        dup: false,
+        qospid: QosPid::AtMostOnce,
+        retain: false,
+        topic_name: "a/\0",
+        payload: b"hello",
+    }
+    .into();
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicName)),
+        encode_slice(&packet, &mut buffer)
+    );
+
+    let packet: Packet = Connect {
+        protocol: Protocol::MQTT311,
+        keep_alive: 60,
+        client_id: "test",
+        clean_session: true,
+        last_will: None,
+        username: Some("\0"),
+        password: None,
+    }
+    .into();
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::Username)),
+        encode_slice(&packet, &mut buffer)
+    );
+
+    let topics: LimitedVec<LimitedString> = [LimitedString::from("a\0b")].iter().cloned().collect();
+    let packet = Unsubscribe::new(Pid::try_from(12321).unwrap(), topics).into();
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicFilter)),
+        encode_slice(&packet, &mut buffer)
+    );
+
+    // Control characters and non-characters.
+    for s in &[
+        "a\nb",
+        "\u{1f}",
+        "\u{9f}",
+        "\u{fdef}",
+        "\u{fffe}",
+        "\u{10ffff}",
+    ] {
+        let packet: Packet = Connect {
+            protocol: Protocol::MQTT311,
+            keep_alive: 60,
+            client_id: s,
+            clean_session: true,
+            last_will: None,
+            username: None,
+            password: None,
+        }
+        .into();
+        assert_eq!(
+            Err(Error::DisallowedCodePoint(Field::ClientId)),
+            encode_slice(&packet, &mut buffer),
+            "{:?}",
+            s
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_encoded_len() {
+    // Around each remaining length size boundary
+    let payload = std::vec![0u8; 2097152];
+    for &len in &[0, 120, 121, 16376, 16377, 2097144, 2097145] {
+        let packet: Packet = Publish {
+            dup: false,
+            qospid: QosPid::AtLeastOnce(Pid::new()),
+            retain: false,
+            topic_name: "a/b",
+            payload: &payload[..len],
+        }
+        .into();
+        let mut buf = BytesMut::new();
+        assert_eq!(Ok(packet.encoded_len()), encode(&packet, &mut buf));
+        assert_eq!(Ok(Some(packet)), decode_slice(&buf));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_encode_append() {
+    let mut buf = BytesMut::new();
+    let packet: Packet = Packet::Pingreq;
+    assert_eq!(Ok(2), encode(&packet, &mut buf));
+    let packet: Packet = Packet::Puback(Pid::try_from(10).unwrap());
+    assert_eq!(Ok(4), encode(&packet, &mut buf));
+    assert_eq!(&buf[..], &[0b11000000, 0, 0b01000000, 2, 0, 10]);
+
+    // Fields are appended in place, and invalid packets don't leave partial bytes
+    let payload = [7u8; 1000];
+    let packet = Publish::builder("a/b", &payload).build().unwrap().into();
+    let mut expected = [0u8; 1008];
+    assert_eq!(Ok(1008), encode_slice(&packet, &mut expected));
+    assert_eq!(Ok(1008), encode(&packet, &mut buf));
+    assert_eq!(&buf[6..], &expected[..]);
+    // Space is reserved once, for the exact size of the packet
+    let mut exact = BytesMut::new();
+    assert_eq!(Ok(1008), encode(&packet, &mut exact));
+    assert_eq!(exact.capacity(), 1008);
+    let packet: Packet = Publish {
+        topic_name: "a\0b",
+        ..Publish::builder("a/b", &payload).build().unwrap()
+    }
+    .into();
+    assert!(encode(&packet, &mut buf).is_err());
+    assert_eq!(buf.len(), 1014);
+}
+
+#[test]
+fn test_connect_mqisdp() {
+    let packet = Connect {
+        protocol: Protocol::MQIsdp,
+        keep_alive: 120,
+        client_id: "imvj",
+        clean_session: true,
+        last_will: None,
+        username: None,
+        password: None,
+    }
+    .into();
+    let mut buf = [0u8; 32];
+    assert_eq!(Ok(20), encode_slice(&packet, &mut buf));
+    assert_eq!(Ok(Some(packet)), decode_slice(&buf[..20]));
+}
+
+#[test]
+fn test_encode_vectored() {
+    let payload = [42u8; 300];
+    for &qospid in &[QosPid::AtMostOnce, QosPid::AtLeastOnce(Pid::new())] {
+        let publish = Publish {
+            dup: false,
+            qospid,
+            retain: true,
+            topic_name: "a/b",
+            payload: &payload,
+        };
+        let mut head = [0u8; 10];
+        let [head, payload] = publish.encode_vectored(&mut head).unwrap();
+        assert_eq!(head.len(), publish.head_len());
+        assert_eq!(payload.as_ptr(), publish.payload.as_ptr());
+
+        let mut buf = [0u8; 400];
+        let len = encode_slice(&publish.clone().into(), &mut buf).unwrap();
+        assert_eq!(&buf[..head.len()], head);
+        assert_eq!(&buf[head.len()..len], payload);
+    }
+
+    let publish = Publish {
+        dup: false,
+        qospid: QosPid::AtMostOnce,
+        retain: false,
+        topic_name: "a/b",
+        payload: &payload,
+    };
+    let mut head = [0u8; 7];
+    assert_eq!(Err(Error::WriteZero), publish.encode_vectored(&mut head));
+}
+
+#[test]
+fn test_packet_writer() {
+    let packets = write_packet_samples();
+    let mut buf = [0u8; 400];
+    let mut writer = PacketWriter::new(&mut buf);
+    let mut ranges = std::vec::Vec::new();
+    for packet in &packets {
+        ranges.push(writer.encode(packet).unwrap());
+    }
+    let written = writer.written();
+    assert_eq!(ranges.last().unwrap().end, written.len());
+    let decoded: std::vec::Vec<_> = PacketIter::new(written).map(Result::unwrap).collect();
+    assert_eq!(decoded, packets);
+    for (range, packet) in ranges.iter().zip(&packets) {
+        assert_eq!(
+            Ok(Some(packet.clone())),
+            decode_slice(&written[range.clone()])
+        );
+    }
+
+    // A packet that doesn't fit leaves the writer untouched
+    let len = writer.len();
+    assert!(writer.remaining() < packets[2].encoded_len());
+    assert_eq!(Err(Error::WriteZero), writer.encode(&packets[2]));
+    assert_eq!(len, writer.len());
+    writer.clear();
+    assert!(writer.is_empty());
+    let pingreq: Packet = Packet::Pingreq;
+    assert_eq!(Ok(0..2), writer.encode(&pingreq));
+}
+
+#[test]
+fn test_encode_many() {
+    let packets = write_packet_samples();
+    let total: usize = packets.iter().map(Packet::encoded_len).sum();
+    let mut buf = std::vec![0u8; total];
+    let ranges: Result<std::vec::Vec<_>, _> = encode_many(&packets, &mut buf).collect();
+    assert_eq!(ranges.unwrap().len(), packets.len());
+
+    // Stops at the first packet that doesn't fit, even if the next one would
+    let mut buf = [0u8; 100];
+    let mut iter = encode_many(&packets, &mut buf);
+    assert!(matches!(iter.next(), Some(Ok(_))));
+    assert!(matches!(iter.next(), Some(Ok(_))));
+    assert_eq!(Some(Err(Error::WriteZero)), iter.next());
+    assert_eq!(None, iter.next());
+}
+
+#[test]
+fn test_publish_writer() {
+    // Each remaining length size, to test the shifting
+    let payload = std::vec![42u8; 20000];
+    for &len in &[0, 10, 200, 20000] {
+        let qospid = QosPid::AtLeastOnce(Pid::new());
+        let mut buf = std::vec![0u8; 20100];
+        let mut writer = PublishWriter::new(&mut buf, "a/b", qospid)
+            .unwrap()
+            .dup(true);
+        writer.write_bytes(&payload[..len / 2]).unwrap();
+        writer.write_bytes(&payload[len / 2..len]).unwrap();
+        let written = writer.finish().unwrap();
+
+        let publish = Publish {
+            dup: true,
+            qospid,
+            retain: false,
+            topic_name: "a/b",
+            payload: &payload[..len],
+        };
+        let mut expected = std::vec![0u8; 20100];
+        let len = encode_slice(&publish.into(), &mut expected).unwrap();
+        assert_eq!(&buf[..written], &expected[..len]);
+    }
+
+    let mut buf = [0u8; 12];
+    assert_eq!(
+        Err(Error::WriteZero),
+        PublishWriter::new(&mut buf[..9], "a/b", QosPid::AtLeastOnce(Pid::new())).map(|_| ())
+    );
+    let mut writer = PublishWriter::new(&mut buf, "a/b", QosPid::AtMostOnce).unwrap();
+    assert_eq!(writer.remaining(), 2);
+    assert_eq!(Err(Error::WriteZero), writer.write_bytes(b"abc"));
+    assert!(core::fmt::Write::write_str(&mut writer, "abc").is_err());
+    writer.write_bytes(b"ab").unwrap();
+    assert_eq!(Ok(9), writer.finish());
+    assert_eq!(
+        &buf[..9],
+        &[0b00110000, 7, 0, 3, b'a', b'/', b'b', b'a', b'b']
+    );
+}
+
+#[test]
+fn test_custom_capacity() {
+    let packet: Packet<8, 64> = SubscribeBuilder::<8, 64>::new(Pid::new())
+        .topic("a/b", QoS::AtMostOnce)
+        .build()
+        .unwrap()
+        .into();
+    let mut expected = [0u8; 16];
+    let len = packet.encode_slice(&mut expected).unwrap();
+
+    let mut buf = [0u8; 16];
+    let mut writer = PacketWriter::new(&mut buf);
+    assert_eq!(Ok(0..len), writer.encode(&packet));
+    assert_eq!(writer.written(), &expected[..len]);
+    let mut buf = [0u8; 16];
+    let ranges: std::vec::Vec<_> = encode_many(core::slice::from_ref(&packet), &mut buf).collect();
+    assert_eq!(ranges, [Ok(0..len)]);
+    #[cfg(feature = "std")]
+    {
+        let mut buf = BytesMut::new();
+        assert_eq!(Ok(len), encode(&packet, &mut buf));
+        assert_eq!(&buf[..], &expected[..len]);
+        let mut out = std::vec::Vec::new();
+        assert_eq!(Ok(len), write_packet(&packet, &mut out));
+        assert_eq!(&out[..], &expected[..len]);
+    }
+}
+
+#[test]
+fn test_const_encoders() {
+    fn encoded(packet: Packet) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; 4];
+        let len = encode_slice(&packet, &mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+    let pid = Pid::try_from(0x1234).unwrap();
+    assert_eq!(encoded(Packet::Pingreq), encode_pingreq());
+    assert_eq!(encoded(Packet::Pingresp), encode_pingresp());
+    assert_eq!(encoded(Packet::Disconnect), encode_disconnect());
+    assert_eq!(encoded(Packet::Puback(pid)), encode_puback(pid));
+    assert_eq!(encoded(Packet::Pubrec(pid)), encode_pubrec(pid));
+    assert_eq!(encoded(Packet::Pubrel(pid)), encode_pubrel(pid));
+    assert_eq!(encoded(Packet::Pubcomp(pid)), encode_pubcomp(pid));
+    assert_eq!(encoded(Packet::Unsuback(pid)), encode_unsuback(pid));
+    for &session_present in &[false, true] {
+        let code = ConnectReturnCode::NotAuthorized;
+        let connack = Connack {
+            session_present,
+            code,
+        };
+        assert_eq!(
+            encoded(connack.into()),
+            encode_connack(session_present, code)
+        );
+    }
+    // Still fails cleanly when the output is too small.
+    let mut buf = [0u8; 3];
+    assert_eq!(
+        Err(Error::WriteZero),
+        encode_slice(&Packet::Puback(pid), &mut buf)
+    );
+}
+
+#[test]
+fn test_builders() {
+    let connect = Connect::builder("")
+        .keep_alive(10)
+        .username("user")
+        .password(b"pass")
+        .build()
+        .unwrap();
+    assert_eq!(connect.username, Some("user"));
+    assert_eq!(
+        Connect::builder("").clean_session(false).build(),
+        Err(Error::InvalidClientId)
+    );
+    assert_eq!(
+        Connect::builder("a\0b").build(),
+        Err(Error::DisallowedCodePoint(Field::ClientId))
+    );
+    let will = LastWill {
+        topic: "status/#",
+        message: b"bye",
+        qos: QoS::AtMostOnce,
+        retain: false,
+    };
+    assert_eq!(
+        Connect::builder("id").will(will).build(),
+        Err(Error::InvalidTopic(Field::WillTopic))
+    );
+
+    assert!(Publish::builder("a/b", b"").build().is_ok());
+    for &topic in &["", "a/#", "a/+/b", "a+"] {
+        assert_eq!(
+            Publish::builder(topic, b"").build(),
+            Err(Error::InvalidTopic(Field::TopicName)),
+            "{:?}",
+            topic
+        );
+    }
+
+    let pid = Pid::new();
+    for &topic in &[
+        "#",
+        "+",
+        "/",
+        "a//b",
+        "+/+",
+        "a/+/b/#",
+        "sport/tennis/player1",
+    ] {
+        assert!(
+            Subscribe::builder(pid)
+                .topic(topic, QoS::AtMostOnce)
+                .build()
+                .is_ok(),
+            "{:?}",
+            topic
+        );
+    }
+    for &topic in &["", "a#", "#/a", "a/#/b", "a+/b", "a/b+", "++"] {
+        assert_eq!(
+            Subscribe::builder(pid)
+                .topic(topic, QoS::AtMostOnce)
+                .build(),
+            Err(Error::InvalidTopic(Field::TopicFilter)),
+            "{:?}",
+            topic
+        );
+    }
+    // The first error wins, even if later topics are valid.
+    let res = Subscribe::builder(pid)
+        .topic("a/#/b", QoS::AtMostOnce)
+        .topic("a\0", QoS::AtMostOnce)
+        .topic("a/b", QoS::AtMostOnce)
+        .build();
+    assert_eq!(res, Err(Error::InvalidTopic(Field::TopicFilter)));
+}
+
+fn write_packet_samples() -> std::vec::Vec<Packet<'static>> {
+    let pid = Pid::try_from(10).unwrap();
+    std::vec![
+        Connect {
+            protocol: Protocol::MQTT311,
+            keep_alive: 120,
+            client_id: "imvj",
+            clean_session: true,
+            last_will: Some(LastWill {
+                topic: "/a",
+                message: b"offline",
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            username: Some("rust"),
+            password: Some(b"mq"),
+        }
+        .into(),
+        Connack {
+            session_present: true,
+            code: ConnectReturnCode::Accepted,
+        }
+        .into(),
+        Publish {
+            dup: true,
+            qospid: QosPid::ExactlyOnce(pid),
+            retain: true,
+            topic_name: "a/b",
+            payload: &[42; 200],
+        }
+        .into(),
+        Packet::Pubrel(pid),
+        Subscribe::new(
+            pid,
+            [SubscribeTopic {
+                topic_path: LimitedString::from("a/+"),
+                qos: QoS::AtLeastOnce,
+            }]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .into(),
+        Suback::new(
+            pid,
+            [
+                SubscribeReturnCodes::Success(QoS::ExactlyOnce),
+                SubscribeReturnCodes::Failure,
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .into(),
+        Unsubscribe::new(pid, [LimitedString::from("a/+")].iter().cloned().collect(),).into(),
+        Packet::Pingreq,
+    ]
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_write_packet() {
+    for packet in write_packet_samples() {
+        let mut out = std::vec::Vec::new();
+        let mut buf = BytesMut::new();
+        assert_eq!(encode(&packet, &mut buf), write_packet(&packet, &mut out));
+        assert_eq!(&buf[..], &out[..]);
+    }
+
+    // Invalid packets aren't partially written
+    let packet: Packet = Publish {
+        dup: false,
+        qospid: QosPid::AtMostOnce,
+        retain: false,
+        topic_name: "a/\0",
+        payload: b"hello",
+    }
+    .into();
+    let mut out = std::vec::Vec::new();
+    assert_eq!(
+        Err(Error::DisallowedCodePoint(Field::TopicName)),
+        write_packet(&packet, &mut out)
+    );
+    assert!(out.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_owned_packet() {
+    for packet in write_packet_samples() {
+        let owned = {
+            let mut buf = std::vec![0u8; packet.encoded_len()];
+            encode_slice(&packet, &mut buf).unwrap();
+            let decoded = decode_slice(&buf).unwrap().unwrap();
+            OwnedPacket::from(decoded)
+        };
+        assert_eq!(packet, owned.as_borrowed());
+        assert_eq!(owned, packet.clone().into_owned());
+        // Can outlive the decoding buffer and move to another thread.
+        let moved = std::thread::spawn(move || owned).join().unwrap();
+        assert_eq!(packet, moved.as_borrowed());
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn test_write_packet_embedded() {
+    for packet in write_packet_samples() {
+        let mut out = [0u8; 512];
+        let len = write_packet_embedded(&packet, &mut out[..]).unwrap();
+        assert_eq!(Ok(Some(packet)), decode_slice(&out[..len]));
+    }
+
+    let mut out = [0u8; 3];
+    let packet: Packet = Packet::Pubrel(Pid::new());
+    assert_eq!(
+        Err(Error::WriteZero),
+        write_packet_embedded(&packet, &mut out[..])
+    );
+}
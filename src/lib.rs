@@ -9,7 +9,6 @@
 //!
 //! ```
 //! use mqttrs::*;
-//! use bytes::BytesMut;
 //!
 //! // Allocate buffer.
 //! let mut buf = [0u8; 1024];
@@ -32,10 +31,42 @@
 //! // Example decode failures.
 //! let mut incomplete = encoded.split_at(10).0;
 //! assert_eq!(Ok(None), decode_slice(&mut incomplete));
-//! let mut garbage = BytesMut::from(&[0u8,0,0,0] as &[u8]);
+//! let mut garbage: &[u8] = &[0u8,0,0,0];
 //! assert_eq!(Err(Error::InvalidHeader), decode_slice(&mut garbage));
 //! ```
 //!
+//! # Large payloads without std
+//!
+//! Without the `std` feature, decoded [Subscribe], [Unsubscribe] and [Suback] packets hold a
+//! limited number of items (see [DecodeOptions::capacity()]). To accept any number of them
+//! without copying, dispatch on [peek_header()] and decode those packets as [SubscribeRef],
+//! [UnsubscribeRef] or [SubackRef] instead:
+//!
+//! ```
+//! # use mqttrs::*;
+//! let opts = DecodeOptions::new().max_packet_size(1024);
+//! let buf = [0b10000010, 6, 0, 10, 0, 1, b'a', 1, 0b11000000, 0];
+//! let mut rest = &buf[..];
+//! let mut topics = 0;
+//! while let Some(fixed) = peek_header(rest).unwrap() {
+//!     let res = match fixed.typ {
+//!         PacketType::Subscribe => SubscribeRef::from_slice(rest, &opts).map(|r| {
+//!             r.map(|(subscribe, len)| {
+//!                 topics += subscribe.topics().filter(Result::is_ok).count();
+//!                 len
+//!             })
+//!         }),
+//!         _ => decode_slice_with_options(rest, &opts).map(|r| r.map(|(_packet, len)| len)),
+//!     };
+//!     match res.unwrap() {
+//!         Some(len) => rest = &rest[len..],
+//!         None => break,
+//!     }
+//! }
+//! assert_eq!(topics, 1);
+//! assert!(rest.is_empty());
+//! ```
+//!
 //! [MQTT 3.1]: http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html
 //! [MQTT 5]: https://docs.oasis-open.org/mqtt/mqtt/v5.0/os/mqtt-v5.0-os.html
 //! [tokio]: https://tokio.rs/
@@ -43,15 +74,27 @@
 //! [encode_slice()]: fn.encode_slice.html
 //! [decode_slice()]: fn.decode_slice.html
 //! [bytes::BytesMut]: https://docs.rs/bytes/1.0.0/bytes/struct.BytesMut.html
+//! [Subscribe]: struct.Subscribe.html
+//! [Unsubscribe]: struct.Unsubscribe.html
+//! [Suback]: struct.Suback.html
+//! [DecodeOptions::capacity()]: struct.DecodeOptions.html#method.capacity
+//! [peek_header()]: fn.peek_header.html
+//! [SubscribeRef]: struct.SubscribeRef.html
+//! [UnsubscribeRef]: struct.UnsubscribeRef.html
+//! [SubackRef]: struct.SubackRef.html
 
 #![cfg_attr(not(test), no_std)]
 
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "std")]
+mod bytes_packet;
 mod connect;
 mod decoder;
 mod encoder;
+#[cfg(feature = "std")]
+mod owned;
 mod packet;
 mod publish;
 mod subscribe;
@@ -68,11 +111,31 @@ mod decoder_test;
 mod encoder_test;
 
 pub use crate::{
-    connect::{Connack, Connect, ConnectReturnCode, LastWill, Protocol},
-    decoder::{clone_packet, decode_slice},
-    encoder::encode_slice,
+    connect::{Connack, Connect, ConnectBuilder, ConnectReturnCode, LastWill, Protocol},
+    decoder::{
+        clone_packet, decode_slice, decode_slice_with_len, decode_slice_with_options,
+        decode_slice_with_warnings, peek_header, DecodeBuffer, DecodeOptions, Decoder, FixedHeader,
+        PacketIter, Warning, Warnings,
+    },
+    encoder::{
+        encode_connack, encode_disconnect, encode_many, encode_pingreq, encode_pingresp,
+        encode_puback, encode_pubcomp, encode_pubrec, encode_pubrel, encode_slice, encode_unsuback,
+        EncodeMany, PacketWriter,
+    },
     packet::{Packet, PacketType},
-    publish::Publish,
-    subscribe::{Suback, Subscribe, SubscribeReturnCodes, SubscribeTopic, Unsubscribe},
-    utils::{Error, Pid, QoS, QosPid},
+    publish::{Publish, PublishBuilder, PublishWriter},
+    subscribe::{
+        PayloadIter, Suback, SubackRef, Subscribe, SubscribeBuilder, SubscribeRef,
+        SubscribeReturnCodes, SubscribeTopic, SubscribeTopicRef, Unsubscribe, UnsubscribeRef,
+    },
+    utils::{Error, Field, Pid, QoS, QosPid},
+};
+
+#[cfg(feature = "embedded-io")]
+pub use crate::encoder::write_packet_embedded;
+#[cfg(feature = "std")]
+pub use crate::{
+    bytes_packet::{decode_bytes, decode_bytes_with_options, BytesPacket, BytesPublish},
+    encoder::{encode, write_packet},
+    owned::{OwnedConnect, OwnedLastWill, OwnedPacket, OwnedPublish},
 };
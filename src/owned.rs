@@ -0,0 +1,254 @@
+use crate::*;
+use std::{string::String, vec::Vec};
+
+/// Owned version of [`LastWill`].
+///
+/// [`LastWill`]: struct.LastWill.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedLastWill {
+    pub topic: String,
+    pub message: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// Owned version of [`Connect`].
+///
+/// [`Connect`]: struct.Connect.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedConnect {
+    pub protocol: Protocol,
+    pub keep_alive: u16,
+    pub client_id: String,
+    pub clean_session: bool,
+    pub last_will: Option<OwnedLastWill>,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+}
+
+/// Owned version of [`Publish`].
+///
+/// [`Publish`]: struct.Publish.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedPublish {
+    pub dup: bool,
+    pub qospid: QosPid,
+    pub retain: bool,
+    pub topic_name: String,
+    pub payload: Vec<u8>,
+}
+
+/// Owned version of [`Packet`], created by [`Packet::into_owned()`].
+///
+/// Decoded packets borrow their strings and payloads from the buffer, which avoids copying but
+/// ties them to its lifetime. An `OwnedPacket` can instead be stored, or sent to another thread.
+/// Only available with the `std` feature.
+///
+/// ```
+/// # use mqttrs::*;
+/// let owned = {
+///     let buf = [0b00110000, 7, 0, 1, b'a', b'h', b'e', b'l', b'o'];
+///     decode_slice(&buf).unwrap().unwrap().into_owned()
+/// };
+/// // The buffer is gone, but the packet lives on.
+/// match &owned {
+///     OwnedPacket::Publish(p) => assert_eq!(p.payload, b"helo"),
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// let mut buf = [0u8; 9];
+/// assert_eq!(Ok(9), encode_slice(&owned.as_borrowed(), &mut buf));
+/// ```
+///
+/// [`Packet`]: enum.Packet.html
+/// [`Packet::into_owned()`]: enum.Packet.html#method.into_owned
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedPacket {
+    Connect(OwnedConnect),
+    Connack(Connack),
+    Publish(OwnedPublish),
+    Puback(Pid),
+    Pubrec(Pid),
+    Pubrel(Pid),
+    Pubcomp(Pid),
+    Subscribe(Subscribe),
+    Suback(Suback),
+    Unsubscribe(Unsubscribe),
+    Unsuback(Pid),
+    Pingreq,
+    Pingresp,
+    Disconnect,
+}
+
+impl<'a> LastWill<'a> {
+    /// Copy the borrowed fields into an [`OwnedLastWill`].
+    ///
+    /// [`OwnedLastWill`]: struct.OwnedLastWill.html
+    pub fn into_owned(self) -> OwnedLastWill {
+        OwnedLastWill {
+            topic: self.topic.into(),
+            message: self.message.into(),
+            qos: self.qos,
+            retain: self.retain,
+        }
+    }
+}
+
+impl OwnedLastWill {
+    /// Borrow the fields as a [`LastWill`].
+    ///
+    /// [`LastWill`]: struct.LastWill.html
+    pub fn as_borrowed(&self) -> LastWill<'_> {
+        LastWill {
+            topic: &self.topic,
+            message: &self.message,
+            qos: self.qos,
+            retain: self.retain,
+        }
+    }
+}
+
+impl<'a> Connect<'a> {
+    /// Copy the borrowed fields into an [`OwnedConnect`].
+    ///
+    /// [`OwnedConnect`]: struct.OwnedConnect.html
+    pub fn into_owned(self) -> OwnedConnect {
+        OwnedConnect {
+            protocol: self.protocol,
+            keep_alive: self.keep_alive,
+            client_id: self.client_id.into(),
+            clean_session: self.clean_session,
+            last_will: self.last_will.map(LastWill::into_owned),
+            username: self.username.map(Into::into),
+            password: self.password.map(Into::into),
+        }
+    }
+}
+
+impl OwnedConnect {
+    /// Borrow the fields as a [`Connect`].
+    ///
+    /// [`Connect`]: struct.Connect.html
+    pub fn as_borrowed(&self) -> Connect<'_> {
+        Connect {
+            protocol: self.protocol,
+            keep_alive: self.keep_alive,
+            client_id: &self.client_id,
+            clean_session: self.clean_session,
+            last_will: self.last_will.as_ref().map(OwnedLastWill::as_borrowed),
+            username: self.username.as_deref(),
+            password: self.password.as_deref(),
+        }
+    }
+}
+
+impl<'a> Publish<'a> {
+    /// Copy the borrowed fields into an [`OwnedPublish`].
+    ///
+    /// [`OwnedPublish`]: struct.OwnedPublish.html
+    pub fn into_owned(self) -> OwnedPublish {
+        OwnedPublish {
+            dup: self.dup,
+            qospid: self.qospid,
+            retain: self.retain,
+            topic_name: self.topic_name.into(),
+            payload: self.payload.into(),
+        }
+    }
+}
+
+impl OwnedPublish {
+    /// Borrow the fields as a [`Publish`].
+    ///
+    /// [`Publish`]: struct.Publish.html
+    pub fn as_borrowed(&self) -> Publish<'_> {
+        Publish {
+            dup: self.dup,
+            qospid: self.qospid,
+            retain: self.retain,
+            topic_name: &self.topic_name,
+            payload: &self.payload,
+        }
+    }
+}
+
+impl<'a> Packet<'a> {
+    /// Copy the borrowed fields into an [`OwnedPacket`], which doesn't depend on the decoding
+    /// buffer anymore.
+    ///
+    /// [`OwnedPacket`]: enum.OwnedPacket.html
+    pub fn into_owned(self) -> OwnedPacket {
+        match self {
+            Packet::Connect(connect) => OwnedPacket::Connect(connect.into_owned()),
+            Packet::Connack(connack) => OwnedPacket::Connack(connack),
+            Packet::Publish(publish) => OwnedPacket::Publish(publish.into_owned()),
+            Packet::Puback(pid) => OwnedPacket::Puback(pid),
+            Packet::Pubrec(pid) => OwnedPacket::Pubrec(pid),
+            Packet::Pubrel(pid) => OwnedPacket::Pubrel(pid),
+            Packet::Pubcomp(pid) => OwnedPacket::Pubcomp(pid),
+            Packet::Subscribe(subscribe) => OwnedPacket::Subscribe(subscribe),
+            Packet::Suback(suback) => OwnedPacket::Suback(suback),
+            Packet::Unsubscribe(unsub) => OwnedPacket::Unsubscribe(unsub),
+            Packet::Unsuback(pid) => OwnedPacket::Unsuback(pid),
+            Packet::Pingreq => OwnedPacket::Pingreq,
+            Packet::Pingresp => OwnedPacket::Pingresp,
+            Packet::Disconnect => OwnedPacket::Disconnect,
+        }
+    }
+}
+
+impl OwnedPacket {
+    /// Borrow the fields as a [`Packet`], for example to encode it.
+    ///
+    /// Not everything is borrowed: [`Packet`] holds [`Subscribe`], [`Suback`] and [`Unsubscribe`]
+    /// by value, so those variants are cloned, copying their topic lists or return codes.
+    ///
+    /// [`Packet`]: enum.Packet.html
+    /// [`Subscribe`]: struct.Subscribe.html
+    /// [`Suback`]: struct.Suback.html
+    /// [`Unsubscribe`]: struct.Unsubscribe.html
+    pub fn as_borrowed(&self) -> Packet<'_> {
+        match self {
+            OwnedPacket::Connect(connect) => Packet::Connect(connect.as_borrowed()),
+            OwnedPacket::Connack(connack) => Packet::Connack(*connack),
+            OwnedPacket::Publish(publish) => Packet::Publish(publish.as_borrowed()),
+            OwnedPacket::Puback(pid) => Packet::Puback(*pid),
+            OwnedPacket::Pubrec(pid) => Packet::Pubrec(*pid),
+            OwnedPacket::Pubrel(pid) => Packet::Pubrel(*pid),
+            OwnedPacket::Pubcomp(pid) => Packet::Pubcomp(*pid),
+            OwnedPacket::Subscribe(subscribe) => Packet::Subscribe(subscribe.clone()),
+            OwnedPacket::Suback(suback) => Packet::Suback(suback.clone()),
+            OwnedPacket::Unsubscribe(unsub) => Packet::Unsubscribe(unsub.clone()),
+            OwnedPacket::Unsuback(pid) => Packet::Unsuback(*pid),
+            OwnedPacket::Pingreq => Packet::Pingreq,
+            OwnedPacket::Pingresp => Packet::Pingresp,
+            OwnedPacket::Disconnect => Packet::Disconnect,
+        }
+    }
+}
+
+impl<'a> From<Packet<'a>> for OwnedPacket {
+    fn from(p: Packet<'a>) -> Self {
+        p.into_owned()
+    }
+}
+
+macro_rules! owned_packet_from {
+    ($($v:ident($t:ident)),+) => {
+        $(
+            impl From<$t> for OwnedPacket {
+                fn from(p: $t) -> Self {
+                    OwnedPacket::$v(p)
+                }
+            }
+        )+
+    }
+}
+
+owned_packet_from!(
+    Connect(OwnedConnect),
+    Publish(OwnedPublish),
+    Connack(Connack),
+    Subscribe(Subscribe),
+    Suback(Suback),
+    Unsubscribe(Unsubscribe)
+);
@@ -1,4 +1,4 @@
-use crate::*;
+use crate::{encoder::packet_len, *};
 
 /// Base enum for all MQTT packet types.
 ///
@@ -9,8 +9,8 @@ use crate::*;
 /// # use mqttrs::*;
 /// # use core::convert::TryFrom;
 /// // Simplest form
-/// let pkt = Packet::Connack(Connack { session_present: false,
-///                                     code: ConnectReturnCode::Accepted });
+/// let pkt: Packet = Packet::Connack(Connack { session_present: false,
+///                                             code: ConnectReturnCode::Accepted });
 /// // Using `Into` trait
 /// let publish = Publish { dup: false,
 ///                         qospid: QosPid::AtMostOnce,
@@ -19,13 +19,30 @@ use crate::*;
 ///                         payload: b"payload" };
 /// let pkt: Packet = publish.into();
 /// // Identifyer-only packets
-/// let pkt = Packet::Puback(Pid::try_from(42).unwrap());
+/// let pkt: Packet = Packet::Puback(Pid::try_from(42).unwrap());
+/// ```
+///
+///
+/// Without the `std` feature, the `Subscribe`, `Suback` and `Unsubscribe` variants hold at most `N`
+/// topics or return codes, of at most `L` bytes each. The free encoding and decoding functions use
+/// the default capacities. Use [`DecodeOptions::capacity()`] and [`Packet::encode_slice()`] for
+/// others:
+///
+/// ```
+/// # use mqttrs::*;
+/// let opts = DecodeOptions::new().capacity::<8, 64>();
+/// let buf = [0b10110000, 2, 0, 10];
+/// let (packet, _): (Packet<8, 64>, _) = decode_slice_with_options(&buf, &opts).unwrap().unwrap();
+/// let mut out = [0u8; 4];
+/// assert_eq!(Ok(4), packet.encode_slice(&mut out));
 /// ```
 ///
 /// [`encode()`]: fn.encode.html
 /// [`decode_slice()`]: fn.decode_slice.html
+/// [`DecodeOptions::capacity()`]: struct.DecodeOptions.html#method.capacity
+/// [`Packet::encode_slice()`]: enum.Packet.html#method.encode_slice
 #[derive(Debug, Clone, PartialEq)]
-pub enum Packet<'a> {
+pub enum Packet<'a, const N: usize = 5, const L: usize = 256> {
     /// [MQTT 3.1](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028)
     Connect(Connect<'a>),
     /// [MQTT 3.2](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718033)
@@ -41,11 +58,11 @@ pub enum Packet<'a> {
     /// [MQTT 3.7](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718058)
     Pubcomp(Pid),
     /// [MQTT 3.8](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718063)
-    Subscribe(Subscribe),
+    Subscribe(Subscribe<N, L>),
     /// [MQTT 3.9](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718068)
-    Suback(Suback),
+    Suback(Suback<N>),
     /// [MQTT 3.10](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718072)
-    Unsubscribe(Unsubscribe),
+    Unsubscribe(Unsubscribe<N, L>),
     /// [MQTT 3.11](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718077)
     Unsuback(Pid),
     /// [MQTT 3.12](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718081)
@@ -55,7 +72,7 @@ pub enum Packet<'a> {
     /// [MQTT 3.14](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718090)
     Disconnect,
 }
-impl<'a> Packet<'a> {
+impl<'a, const N: usize, const L: usize> Packet<'a, N, L> {
     /// Return the packet type variant.
     ///
     /// This can be used for matching, categorising, debuging, etc. Most users will match directly
@@ -78,12 +95,47 @@ impl<'a> Packet<'a> {
             Packet::Disconnect => PacketType::Disconnect,
         }
     }
+
+    /// Return the size of the encoded packet, fixed header included.
+    ///
+    /// This is the number of bytes written by [`encode_slice()`], and can be used to allocate an
+    /// exact buffer, or to check a maximum packet size before encoding.
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// let pkt: Packet = Publish { dup: false,
+    ///                             qospid: QosPid::AtMostOnce,
+    ///                             retain: false,
+    ///                             topic_name: "to/pic",
+    ///                             payload: b"payload" }.into();
+    /// let mut buf = [0u8; 64];
+    /// assert_eq!(17, pkt.encoded_len());
+    /// assert_eq!(Ok(pkt.encoded_len()), encode_slice(&pkt, &mut buf));
+    /// ```
+    ///
+    /// [`encode_slice()`]: fn.encode_slice.html
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Packet::Connect(connect) => connect.encoded_len(),
+            Packet::Connack(connack) => connack.encoded_len(),
+            Packet::Publish(publish) => publish.encoded_len(),
+            Packet::Subscribe(subscribe) => subscribe.encoded_len(),
+            Packet::Suback(suback) => suback.encoded_len(),
+            Packet::Unsubscribe(unsub) => unsub.encoded_len(),
+            Packet::Puback(_)
+            | Packet::Pubrec(_)
+            | Packet::Pubrel(_)
+            | Packet::Pubcomp(_)
+            | Packet::Unsuback(_) => packet_len(2),
+            Packet::Pingreq | Packet::Pingresp | Packet::Disconnect => packet_len(0),
+        }
+    }
 }
 
 macro_rules! packet_from_borrowed {
     ($($t:ident),+) => {
         $(
-            impl<'a> From<$t<'a>> for Packet<'a> {
+            impl<'a, const N: usize, const L: usize> From<$t<'a>> for Packet<'a, N, L> {
                 fn from(p: $t<'a>) -> Self {
                     Packet::$t(p)
                 }
@@ -92,11 +144,11 @@ macro_rules! packet_from_borrowed {
     }
 }
 macro_rules! packet_from {
-    ($($t:ident),+) => {
+    ($($t:ty => $v:ident),+) => {
         $(
-            impl<'a> From<$t> for Packet<'a> {
+            impl<'a, const N: usize, const L: usize> From<$t> for Packet<'a, N, L> {
                 fn from(p: $t) -> Self {
-                    Packet::$t(p)
+                    Packet::$v(p)
                 }
             }
         )+
@@ -104,7 +156,12 @@ macro_rules! packet_from {
 }
 
 packet_from_borrowed!(Connect, Publish);
-packet_from!(Suback, Connack, Subscribe, Unsubscribe);
+packet_from!(
+    Suback<N> => Suback,
+    Connack => Connack,
+    Subscribe<N, L> => Subscribe,
+    Unsubscribe<N, L> => Unsubscribe
+);
 
 /// Packet type variant, without the associated data.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -13,14 +13,43 @@ pub struct Publish<'a> {
 }
 
 impl<'a> Publish<'a> {
+    /// Start building a `Publish` at QoS 0, checked by [`PublishBuilder::build()`].
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// let publish = Publish::builder("sensors/1/temp", b"21.5")
+    ///     .qospid(QosPid::AtLeastOnce(Pid::new()))
+    ///     .retain(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(publish.qospid.qos(), QoS::AtLeastOnce);
+    ///
+    /// let res = Publish::builder("sensors/+/temp", b"21.5").build();
+    /// assert_eq!(res, Err(Error::InvalidTopic(Field::TopicName)));
+    /// ```
+    ///
+    /// [`PublishBuilder::build()`]: struct.PublishBuilder.html#method.build
+    pub fn builder(topic_name: &'a str, payload: &'a [u8]) -> PublishBuilder<'a> {
+        PublishBuilder {
+            publish: Publish {
+                dup: false,
+                qospid: QosPid::AtMostOnce,
+                retain: false,
+                topic_name,
+                payload,
+            },
+        }
+    }
+
     pub(crate) fn from_buffer(
         header: &Header,
         remaining_len: usize,
         buf: &'a [u8],
         offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
     ) -> Result<Self, Error> {
         let payload_end = *offset + remaining_len;
-        let topic_name = read_str(buf, offset)?;
+        let topic_name = read_str(buf, offset, Field::TopicName, ctx)?;
 
         let qospid = match header.qos {
             QoS::AtMostOnce => QosPid::AtMostOnce,
@@ -28,42 +57,90 @@ impl<'a> Publish<'a> {
             QoS::ExactlyOnce => QosPid::ExactlyOnce(Pid::from_buffer(buf, offset)?),
         };
 
+        let payload = buf.get(*offset..payload_end).ok_or(Error::TruncatedBody)?;
+        *offset = payload_end;
+
         Ok(Publish {
             dup: header.dup,
             qospid,
             retain: header.retain,
             topic_name,
-            payload: &buf[*offset..payload_end],
+            payload,
         })
     }
-    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
-        // Header
-        let mut header: u8 = match self.qospid {
-            QosPid::AtMostOnce => 0b00110000,
-            QosPid::AtLeastOnce(_) => 0b00110010,
-            QosPid::ExactlyOnce(_) => 0b00110100,
-        };
-        if self.dup {
-            header |= 0b00001000 as u8;
-        };
-        if self.retain {
-            header |= 0b00000001 as u8;
-        };
-        check_remaining(buf, offset, 1)?;
-        write_u8(buf, offset, header)?;
-
-        // Length: topic (2+len) + pid (0/2) + payload (len)
-        let length = self.topic_name.len()
-            + match self.qospid {
-                QosPid::AtMostOnce => 2,
-                _ => 4,
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Return the size of the encoded fixed header, topic and pid, which is the buffer size needed
+    /// by [`encode_vectored()`].
+    ///
+    /// [`encode_vectored()`]: #method.encode_vectored
+    pub fn head_len(&self) -> usize {
+        self.encoded_len() - self.payload.len()
+    }
+
+    /// Encode the fixed header, topic and pid into `buf`, and return them alongside the payload,
+    /// which isn't copied.
+    ///
+    /// Both slices are meant to be sent one after the other, for example using
+    /// `write_vectored()` or a DMA scatter list.
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// # use std::io::{IoSlice, Write};
+    /// let publish = Publish {
+    ///    dup: false,
+    ///    qospid: QosPid::AtMostOnce,
+    ///    retain: false,
+    ///    topic_name: "test",
+    ///    payload: b"hello",
+    /// };
+    ///
+    /// let mut head = [0u8; 16];
+    /// let [head, payload] = publish.encode_vectored(&mut head).unwrap();
+    /// assert_eq!(head, &[0b00110000, 11, 0, 4, b't', b'e', b's', b't']);
+    /// assert_eq!(payload, b"hello");
+    ///
+    /// let mut out = Vec::new();
+    /// out.write_vectored(&[IoSlice::new(head), IoSlice::new(payload)]).unwrap();
+    /// assert_eq!(Ok(Some(publish.into())), decode_slice(&out));
+    /// ```
+    pub fn encode_vectored<'b>(&'b self, buf: &'b mut [u8]) -> Result<[&'b [u8]; 2], Error> {
+        let mut offset = 0;
+        check_remaining(buf, &mut offset, self.head_len())?;
+        write_header(buf, &mut offset, self.header(), self.remaining_len())?;
+        write_string(buf, &mut offset, self.topic_name, Field::TopicName)?;
+        match self.qospid {
+            QosPid::AtMostOnce => (),
+            QosPid::AtLeastOnce(pid) | QosPid::ExactlyOnce(pid) => {
+                pid.to_buffer(buf, &mut offset)?
             }
-            + self.payload.len();
+        }
+        Ok([&buf[..offset], self.payload])
+    }
 
-        let write_len = write_length(buf, offset, length)? + 1;
+    /// Length of the packet, without the fixed header.
+    pub(crate) fn remaining_len(&self) -> usize {
+        // topic (2+len) + pid (0/2) + payload (len)
+        let pid_len = match self.qospid {
+            QosPid::AtMostOnce => 0,
+            _ => 2,
+        };
+        2 + self.topic_name.len() + pid_len + self.payload.len()
+    }
+
+    fn header(&self) -> u8 {
+        header(self.dup, self.qospid, self.retain)
+    }
+
+    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        // Header
+        let write_len = start_packet(buf, offset, self.header(), self.remaining_len())?;
 
         // Topic
-        write_string(buf, offset, self.topic_name)?;
+        write_string(buf, offset, self.topic_name, Field::TopicName)?;
 
         // Pid
         match self.qospid {
@@ -73,10 +150,208 @@ impl<'a> Publish<'a> {
         }
 
         // Payload
-        for &byte in self.payload {
-            write_u8(buf, offset, byte)?;
+        write_slice(buf, offset, self.payload)?;
+
+        Ok(write_len)
+    }
+
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub(crate) fn to_sink(&self, sink: &mut impl Sink) -> Result<usize, Error> {
+        utils::check_str(self.topic_name, Field::TopicName)?;
+
+        // Header and topic length
+        let mut head = [0u8; 5 + 2];
+        let mut offset = 0;
+        let write_len = write_header(&mut head, &mut offset, self.header(), self.remaining_len())?;
+        write_u16(&mut head, &mut offset, self.topic_name.len() as u16)?;
+        sink.write_all(&head[..offset])?;
+
+        sink.write_all(self.topic_name.as_bytes())?;
+        match self.qospid {
+            QosPid::AtMostOnce => (),
+            QosPid::AtLeastOnce(pid) | QosPid::ExactlyOnce(pid) => {
+                sink.write_all(&pid.get().to_be_bytes())?
+            }
         }
+        sink.write_all(self.payload)?;
 
         Ok(write_len)
     }
 }
+
+/// Fixed header byte of a [`Publish`] packet.
+///
+/// [`Publish`]: struct.Publish.html
+fn header(dup: bool, qospid: QosPid, retain: bool) -> u8 {
+    let mut header: u8 = match qospid {
+        QosPid::AtMostOnce => 0b00110000,
+        QosPid::AtLeastOnce(_) => 0b00110010,
+        QosPid::ExactlyOnce(_) => 0b00110100,
+    };
+    if dup {
+        header |= 0b00001000_u8;
+    };
+    if retain {
+        header |= 0b00000001_u8;
+    };
+    header
+}
+
+/// Builder for a [`Publish`] packet, created by [`Publish::builder()`].
+///
+/// [`Publish`]: struct.Publish.html
+/// [`Publish::builder()`]: struct.Publish.html#method.builder
+#[derive(Debug, Clone)]
+pub struct PublishBuilder<'a> {
+    publish: Publish<'a>,
+}
+
+impl<'a> PublishBuilder<'a> {
+    pub fn qospid(mut self, qospid: QosPid) -> Self {
+        self.publish.qospid = qospid;
+        self
+    }
+
+    pub fn dup(mut self, dup: bool) -> Self {
+        self.publish.dup = dup;
+        self
+    }
+
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.publish.retain = retain;
+        self
+    }
+
+    /// Check the fields and return the `Publish`.
+    ///
+    /// Fails if the topic name is empty, contains wildcards or is too long, or if the packet
+    /// exceeds the MQTT size limit.
+    pub fn build(self) -> Result<Publish<'a>, Error> {
+        let publish = self.publish;
+        utils::check_topic_name(publish.topic_name, Field::TopicName)?;
+        if publish.remaining_len() > MAX_REMAINING_LEN {
+            return Err(Error::PacketTooLarge);
+        }
+        Ok(publish)
+    }
+}
+
+/// Encode a [`Publish`] packet whose payload is written directly into the output buffer.
+///
+/// The fixed header is only known once the payload is complete, so space is reserved for the
+/// biggest possible header, and [`finish()`] moves the packet to the start of the buffer if the
+/// header turned out smaller. The payload can be written using [`write_bytes()`] or
+/// `core::fmt::Write`.
+///
+/// ```
+/// # use mqttrs::*;
+/// use core::fmt::Write;
+///
+/// let mut buf = [0u8; 64];
+/// let mut writer = PublishWriter::new(&mut buf, "sensor/temp", QosPid::AtMostOnce)
+///     .unwrap()
+///     .retain(true);
+/// write!(writer, "{:.1}", 21.54).unwrap();
+/// let len = writer.finish().unwrap();
+///
+/// match decode_slice(&buf[..len]) {
+///     Ok(Some(Packet::Publish(p))) => {
+///         assert_eq!(p.topic_name, "sensor/temp");
+///         assert_eq!(p.payload, b"21.5");
+///         assert!(p.retain);
+///     }
+///     other => panic!("Failed decode: {:?}", other),
+/// }
+/// ```
+///
+/// [`Publish`]: struct.Publish.html
+/// [`finish()`]: struct.PublishWriter.html#method.finish
+/// [`write_bytes()`]: struct.PublishWriter.html#method.write_bytes
+#[derive(Debug)]
+pub struct PublishWriter<'b> {
+    buf: &'b mut [u8],
+    qospid: QosPid,
+    dup: bool,
+    retain: bool,
+    len: usize,
+}
+
+/// Space reserved for the fixed header: header byte and the longest remaining length.
+const MAX_HEADER_LEN: usize = 5;
+
+impl<'b> PublishWriter<'b> {
+    /// Start a packet in `buf`, writing the topic and pid after the space reserved for the fixed
+    /// header.
+    pub fn new(buf: &'b mut [u8], topic_name: &str, qospid: QosPid) -> Result<Self, Error> {
+        let pid_len = match qospid {
+            QosPid::AtMostOnce => 0,
+            _ => 2,
+        };
+        if buf.len() < MAX_HEADER_LEN + 2 + topic_name.len() + pid_len {
+            return Err(Error::WriteZero);
+        }
+        let mut offset = MAX_HEADER_LEN;
+        write_string(buf, &mut offset, topic_name, Field::TopicName)?;
+        match qospid {
+            QosPid::AtMostOnce => (),
+            QosPid::AtLeastOnce(pid) | QosPid::ExactlyOnce(pid) => {
+                pid.to_buffer(buf, &mut offset)?
+            }
+        }
+        Ok(PublishWriter {
+            buf,
+            qospid,
+            dup: false,
+            retain: false,
+            len: offset,
+        })
+    }
+
+    /// Set the `dup` flag.
+    pub fn dup(mut self, dup: bool) -> Self {
+        self.dup = dup;
+        self
+    }
+
+    /// Set the `retain` flag.
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Append `bytes` to the payload, or return `Error::WriteZero` (leaving the payload untouched)
+    /// if they don't fit.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::WriteZero)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Number of bytes still available for the payload.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Write the fixed header and move the packet to the start of the buffer, returning its size.
+    pub fn finish(self) -> Result<usize, Error> {
+        let remaining_len = self.len - MAX_HEADER_LEN;
+        let start = MAX_HEADER_LEN - 1 - length_size(remaining_len);
+        let header = header(self.dup, self.qospid, self.retain);
+        let mut offset = start;
+        write_header(self.buf, &mut offset, header, remaining_len)?;
+        if start > 0 {
+            self.buf.copy_within(start..self.len, 0);
+        }
+        Ok(self.len - start)
+    }
+}
+
+impl<'b> core::fmt::Write for PublishWriter<'b> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
@@ -2,33 +2,62 @@ use crate::{decoder::*, encoder::*, *};
 #[cfg(feature = "derive")]
 use serde::{Deserialize, Serialize};
 
+// The capacities are only used without `std`.
 #[cfg(feature = "std")]
-pub(crate) type LimitedVec<T> = std::vec::Vec<T>;
+pub(crate) type LimitedVec<T, const N: usize = 5> = std::vec::Vec<T>;
 #[cfg(not(feature = "std"))]
-pub(crate) type LimitedVec<T> = heapless::Vec<T, 5>;
+pub(crate) type LimitedVec<T, const N: usize = 5> = heapless::Vec<T, N>;
 
 #[cfg(feature = "std")]
-pub(crate) type LimitedString = std::string::String;
+pub(crate) type LimitedString<const L: usize = 256> = std::string::String;
 #[cfg(not(feature = "std"))]
-pub(crate) type LimitedString = heapless::String<256>;
+pub(crate) type LimitedString<const L: usize = 256> = heapless::String<L>;
+
+/// Copy a decoded string, failing instead of panicking if it doesn't fit.
+fn limited_string<const L: usize>(s: &str) -> Result<LimitedString<L>, Error> {
+    #[cfg(feature = "std")]
+    return Ok(LimitedString::from(s));
+    #[cfg(not(feature = "std"))]
+    {
+        let mut string = LimitedString::new();
+        string.push_str(s).map_err(|_| Error::InvalidLength)?;
+        Ok(string)
+    }
+}
+
+/// Write the fixed header and pid of a packet to `sink`.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn sink_header_pid(sink: &mut impl Sink, header: u8, len: usize, pid: Pid) -> Result<usize, Error> {
+    let mut head = [0u8; 5 + 2];
+    let mut offset = 0;
+    let write_len = write_header(&mut head, &mut offset, header, len)?;
+    pid.to_buffer(&mut head, &mut offset)?;
+    sink.write_all(&head[..offset])?;
+    Ok(write_len)
+}
 
 /// Subscribe topic.
 ///
-/// [Subscribe] packets contain a `Vec` of those.
+/// [Subscribe] packets contain a `Vec` of those. Without the `std` feature, `topic_path` is a
+/// `heapless::String<L>`.
 ///
 /// [Subscribe]: struct.Subscribe.html
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
-pub struct SubscribeTopic {
-    pub topic_path: LimitedString,
+pub struct SubscribeTopic<const L: usize = 256> {
+    pub topic_path: LimitedString<L>,
     pub qos: QoS,
 }
 
-impl SubscribeTopic {
-    pub(crate) fn from_buffer(buf: &[u8], offset: &mut usize) -> Result<Self, Error> {
-        let topic_path = LimitedString::from(read_str(buf, offset)?);
-        let qos = QoS::from_u8(buf[*offset])?;
-        *offset += 1;
+impl<const L: usize> SubscribeTopic<L> {
+    pub(crate) fn from_buffer<'a>(
+        buf: &'a [u8],
+        offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
+    ) -> Result<Self, Error> {
+        let topic_path = limited_string::<L>(read_str(buf, offset, Field::TopicFilter, ctx)?)?;
+        // Values above 2, including those with reserved bits set, are rejected ([MQTT-3.8.3-4]).
+        let qos = QoS::from_u8(read_u8(buf, offset)?)?;
         Ok(SubscribeTopic { topic_path, qos })
     }
 }
@@ -45,9 +74,8 @@ pub enum SubscribeReturnCodes {
 }
 
 impl SubscribeReturnCodes {
-    pub(crate) fn from_buffer<'a>(buf: &'a [u8], offset: &mut usize) -> Result<Self, Error> {
-        let code = buf[*offset];
-        *offset += 1;
+    pub(crate) fn from_buffer(buf: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        let code = read_u8(buf, offset)?;
 
         if code == 0x80 {
             Ok(SubscribeReturnCodes::Failure)
@@ -56,8 +84,8 @@ impl SubscribeReturnCodes {
         }
     }
 
-    pub(crate) fn to_u8(&self) -> u8 {
-        match *self {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
             SubscribeReturnCodes::Failure => 0x80,
             SubscribeReturnCodes::Success(qos) => qos.to_u8(),
         }
@@ -66,124 +94,269 @@ impl SubscribeReturnCodes {
 
 /// Subscribe packet ([MQTT 3.8]).
 ///
+/// Without the `std` feature, `topics` is a `heapless::Vec` of at most `N` topics of at most `L`
+/// bytes. Decoding a packet that doesn't fit fails with `Error::InvalidLength`. The capacities are
+/// chosen with [`DecodeOptions::capacity()`].
+///
 /// [MQTT 3.8]: http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718063
+/// [`DecodeOptions::capacity()`]: struct.DecodeOptions.html#method.capacity
 #[derive(Debug, Clone, PartialEq)]
-pub struct Subscribe {
+pub struct Subscribe<const N: usize = 5, const L: usize = 256> {
     pub pid: Pid,
-    pub topics: LimitedVec<SubscribeTopic>,
+    pub topics: LimitedVec<SubscribeTopic<L>, N>,
 }
 
 /// Subsack packet ([MQTT 3.9]).
 ///
+/// Without the `std` feature, `return_codes` is a `heapless::Vec` of at most `N` return codes.
+///
 /// [MQTT 3.9]: http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718068
 #[derive(Debug, Clone, PartialEq)]
-pub struct Suback {
+pub struct Suback<const N: usize = 5> {
     pub pid: Pid,
-    pub return_codes: LimitedVec<SubscribeReturnCodes>,
+    pub return_codes: LimitedVec<SubscribeReturnCodes, N>,
 }
 
 /// Unsubscribe packet ([MQTT 3.10]).
 ///
+/// Without the `std` feature, `topics` is a `heapless::Vec` of at most `N` topics of at most `L`
+/// bytes.
+///
 /// [MQTT 3.10]: http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718072
 #[derive(Debug, Clone, PartialEq)]
-pub struct Unsubscribe {
+pub struct Unsubscribe<const N: usize = 5, const L: usize = 256> {
     pub pid: Pid,
-    pub topics: LimitedVec<LimitedString>,
+    pub topics: LimitedVec<LimitedString<L>, N>,
 }
 
 impl Subscribe {
-    pub fn new(pid: Pid, topics: LimitedVec<SubscribeTopic>) -> Self {
+    /// Start building a `Subscribe`, checked by [`SubscribeBuilder::build()`].
+    ///
+    /// This uses the default capacities, see [`SubscribeBuilder::new()`] for others.
+    ///
+    /// ```
+    /// # use mqttrs::*;
+    /// let subscribe = Subscribe::builder(Pid::new())
+    ///     .topic("sensors/+/temp", QoS::AtLeastOnce)
+    ///     .topic("commands/#", QoS::ExactlyOnce)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(subscribe.topics.len(), 2);
+    ///
+    /// let res = Subscribe::builder(Pid::new()).topic("commands/#/all", QoS::AtMostOnce).build();
+    /// assert_eq!(res, Err(Error::InvalidTopic(Field::TopicFilter)));
+    /// assert_eq!(Subscribe::builder(Pid::new()).build(), Err(Error::EmptyPayload));
+    /// ```
+    ///
+    /// [`SubscribeBuilder::build()`]: struct.SubscribeBuilder.html#method.build
+    /// [`SubscribeBuilder::new()`]: struct.SubscribeBuilder.html#method.new
+    pub fn builder(pid: Pid) -> SubscribeBuilder {
+        SubscribeBuilder::new(pid)
+    }
+}
+
+impl<const N: usize, const L: usize> Subscribe<N, L> {
+    pub fn new(pid: Pid, topics: LimitedVec<SubscribeTopic<L>, N>) -> Self {
         Subscribe { pid, topics }
     }
 
-    pub(crate) fn from_buffer(
+    pub(crate) fn from_buffer<'a>(
         remaining_len: usize,
-        buf: &[u8],
+        buf: &'a [u8],
         offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
     ) -> Result<Self, Error> {
         let payload_end = *offset + remaining_len;
         let pid = Pid::from_buffer(buf, offset)?;
 
         let mut topics = LimitedVec::new();
         while *offset < payload_end {
-            let _res = topics.push(SubscribeTopic::from_buffer(buf, offset)?);
-
+            let item = SubscribeTopic::from_buffer(buf, offset, ctx)?;
+            #[cfg(feature = "std")]
+            topics.push(item);
             #[cfg(not(feature = "std"))]
-            _res.map_err(|_| Error::InvalidLength)?;
+            topics.push(item).map_err(|_| Error::InvalidLength)?;
         }
 
+        if topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
         Ok(Subscribe { pid, topics })
     }
 
-    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
-        let header: u8 = 0b10000010;
-        check_remaining(buf, offset, 1)?;
-        write_u8(buf, offset, header)?;
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
 
-        // Length: pid(2) + topic.for_each(2+len + qos(1))
-        let mut length = 2;
-        for topic in &self.topics {
-            length += topic.topic_path.len() + 2 + 1;
+    /// Length of the packet, without the fixed header.
+    pub(crate) fn remaining_len(&self) -> usize {
+        // pid(2) + topic.for_each(2+len + qos(1))
+        let topics: usize = self.topics.iter().map(|t| 2 + t.topic_path.len() + 1).sum();
+        2 + topics
+    }
+
+    pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        if self.topics.is_empty() {
+            return Err(Error::EmptyPayload);
         }
-        let write_len = write_length(buf, offset, length)? + 1;
+        let header: u8 = 0b10000010;
+        let write_len = start_packet(buf, offset, header, self.remaining_len())?;
 
         // Pid
         self.pid.to_buffer(buf, offset)?;
 
         // Topics
         for topic in &self.topics {
-            write_string(buf, offset, topic.topic_path.as_str())?;
+            write_string(buf, offset, topic.topic_path.as_str(), Field::TopicFilter)?;
             write_u8(buf, offset, topic.qos.to_u8())?;
         }
 
         Ok(write_len)
     }
+
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub(crate) fn to_sink(&self, sink: &mut impl Sink) -> Result<usize, Error> {
+        if self.topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
+        for topic in &self.topics {
+            utils::check_str(topic.topic_path.as_str(), Field::TopicFilter)?;
+        }
+
+        let write_len = sink_header_pid(sink, 0b10000010, self.remaining_len(), self.pid)?;
+        for topic in &self.topics {
+            sink_string(sink, topic.topic_path.as_str(), Field::TopicFilter)?;
+            sink.write_all(&[topic.qos.to_u8()])?;
+        }
+        Ok(write_len)
+    }
 }
 
-impl Unsubscribe {
-    pub fn new(pid: Pid, topics: LimitedVec<LimitedString>) -> Self {
+/// Builder for a [`Subscribe`] packet, created by [`Subscribe::builder()`].
+///
+/// [`Subscribe`]: struct.Subscribe.html
+/// [`Subscribe::builder()`]: struct.Subscribe.html#method.builder
+#[derive(Debug, Clone)]
+pub struct SubscribeBuilder<const N: usize = 5, const L: usize = 256> {
+    /// The first error is kept until `build()`, so that `topic()` can be chained.
+    subscribe: Result<Subscribe<N, L>, Error>,
+}
+
+impl<const N: usize, const L: usize> SubscribeBuilder<N, L> {
+    /// Start building a `Subscribe` with custom capacities, for example
+    /// `SubscribeBuilder::<8, 64>::new(pid)`.
+    pub fn new(pid: Pid) -> Self {
+        SubscribeBuilder {
+            subscribe: Ok(Subscribe::new(pid, LimitedVec::new())),
+        }
+    }
+
+    /// Add a topic filter, subscribed to with a maximum `qos`.
+    pub fn topic(mut self, topic_path: &str, qos: QoS) -> Self {
+        self.subscribe = self.subscribe.and_then(|mut subscribe| {
+            utils::check_topic_filter(topic_path)?;
+            let topic_path = limited_string::<L>(topic_path)?;
+            let topic = SubscribeTopic { topic_path, qos };
+            #[cfg(feature = "std")]
+            subscribe.topics.push(topic);
+            #[cfg(not(feature = "std"))]
+            subscribe
+                .topics
+                .push(topic)
+                .map_err(|_| Error::InvalidLength)?;
+            Ok(subscribe)
+        });
+        self
+    }
+
+    /// Check the topics and return the `Subscribe`.
+    ///
+    /// Fails if there are no topics, if a topic filter is empty, too long, or has misplaced
+    /// wildcards, or if the topics don't fit in the `no_std` capacity.
+    pub fn build(self) -> Result<Subscribe<N, L>, Error> {
+        let subscribe = self.subscribe?;
+        if subscribe.topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
+        Ok(subscribe)
+    }
+}
+
+impl<const N: usize, const L: usize> Unsubscribe<N, L> {
+    pub fn new(pid: Pid, topics: LimitedVec<LimitedString<L>, N>) -> Self {
         Unsubscribe { pid, topics }
     }
 
-    pub(crate) fn from_buffer(
+    pub(crate) fn from_buffer<'a>(
         remaining_len: usize,
-        buf: &[u8],
+        buf: &'a [u8],
         offset: &mut usize,
+        ctx: &mut DecodeCtx<'a, '_>,
     ) -> Result<Self, Error> {
         let payload_end = *offset + remaining_len;
         let pid = Pid::from_buffer(buf, offset)?;
 
         let mut topics = LimitedVec::new();
         while *offset < payload_end {
-            let _res = topics.push(LimitedString::from(read_str(buf, offset)?));
-
+            let item = limited_string::<L>(read_str(buf, offset, Field::TopicFilter, ctx)?)?;
+            #[cfg(feature = "std")]
+            topics.push(item);
             #[cfg(not(feature = "std"))]
-            _res.map_err(|_| Error::InvalidLength)?;
+            topics.push(item).map_err(|_| Error::InvalidLength)?;
         }
 
+        if topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
         Ok(Unsubscribe { pid, topics })
     }
 
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Length of the packet, without the fixed header.
+    pub(crate) fn remaining_len(&self) -> usize {
+        // pid(2) + topic.for_each(2+len)
+        let topics: usize = self.topics.iter().map(|t| 2 + t.len()).sum();
+        2 + topics
+    }
+
     pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        if self.topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
         let header: u8 = 0b10100010;
-        let mut length = 2;
+        let length = self.remaining_len();
+        let write_len = start_packet(buf, offset, header, length)?;
+        self.pid.to_buffer(buf, offset)?;
         for topic in &self.topics {
-            length += 2 + topic.len();
+            write_string(buf, offset, topic, Field::TopicFilter)?;
         }
-        check_remaining(buf, offset, 1)?;
-        write_u8(buf, offset, header)?;
+        Ok(write_len)
+    }
 
-        let write_len = write_length(buf, offset, length)? + 1;
-        self.pid.to_buffer(buf, offset)?;
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub(crate) fn to_sink(&self, sink: &mut impl Sink) -> Result<usize, Error> {
+        if self.topics.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
+        for topic in &self.topics {
+            utils::check_str(topic, Field::TopicFilter)?;
+        }
+
+        let write_len = sink_header_pid(sink, 0b10100010, self.remaining_len(), self.pid)?;
         for topic in &self.topics {
-            write_string(buf, offset, topic)?;
+            sink_string(sink, topic, Field::TopicFilter)?;
         }
         Ok(write_len)
     }
 }
 
-impl Suback {
-    pub fn new(pid: Pid, return_codes: LimitedVec<SubscribeReturnCodes>) -> Self {
+impl<const N: usize> Suback<N> {
+    pub fn new(pid: Pid, return_codes: LimitedVec<SubscribeReturnCodes, N>) -> Self {
         Suback { pid, return_codes }
     }
 
@@ -197,26 +370,294 @@ impl Suback {
 
         let mut return_codes = LimitedVec::new();
         while *offset < payload_end {
-            let _res = return_codes.push(SubscribeReturnCodes::from_buffer(buf, offset)?);
-
+            let item = SubscribeReturnCodes::from_buffer(buf, offset)?;
+            #[cfg(feature = "std")]
+            return_codes.push(item);
             #[cfg(not(feature = "std"))]
-            _res.map_err(|_| Error::InvalidLength)?;
+            return_codes.push(item).map_err(|_| Error::InvalidLength)?;
         }
 
+        if return_codes.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
         Ok(Suback { pid, return_codes })
     }
 
+    /// Return the size of the encoded packet, fixed header included.
+    pub fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Length of the packet, without the fixed header.
+    pub(crate) fn remaining_len(&self) -> usize {
+        // pid(2) + return_codes.for_each(1)
+        2 + self.return_codes.len()
+    }
+
     pub(crate) fn to_buffer(&self, buf: &mut [u8], offset: &mut usize) -> Result<usize, Error> {
+        if self.return_codes.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
         let header: u8 = 0b10010000;
-        let length = 2 + self.return_codes.len();
-        check_remaining(buf, offset, 1)?;
-        write_u8(buf, offset, header)?;
-
-        let write_len = write_length(buf, offset, length)? + 1;
+        let length = self.remaining_len();
+        let write_len = start_packet(buf, offset, header, length)?;
         self.pid.to_buffer(buf, offset)?;
         for rc in &self.return_codes {
             write_u8(buf, offset, rc.to_u8())?;
         }
         Ok(write_len)
     }
+
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub(crate) fn to_sink(&self, sink: &mut impl Sink) -> Result<usize, Error> {
+        if self.return_codes.is_empty() {
+            return Err(Error::EmptyPayload);
+        }
+
+        let write_len = sink_header_pid(sink, 0b10010000, self.remaining_len(), self.pid)?;
+        for rc in &self.return_codes {
+            sink.write_all(&[rc.to_u8()])?;
+        }
+        Ok(write_len)
+    }
+}
+
+/// Read a complete packet of type `typ` from `buf`, passing its pid, the rest of its body and
+/// whether decoding is lenient to `new`, and returning its total length.
+fn read_pid_frame<'a, T, const N: usize, const L: usize>(
+    buf: &'a [u8],
+    opts: &DecodeOptions<N, L>,
+    typ: PacketType,
+    new: fn(Pid, &'a [u8], bool) -> T,
+) -> Result<Option<(T, usize)>, Error> {
+    let fixed = match parse_header(buf, opts.lenient)? {
+        Some((fixed, _)) if fixed.typ != typ => return Err(Error::InvalidHeader),
+        Some((fixed, _)) => fixed,
+        None => return Ok(None),
+    };
+    let len = fixed.packet_len();
+    opts.check_size(typ, len)?;
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let body = &buf[fixed.header_len..len];
+    let mut offset = 0;
+    let pid = Pid::from_buffer(body, &mut offset)?;
+    if offset == body.len() {
+        return Err(Error::EmptyPayload);
+    }
+    Ok(Some((new(pid, &body[offset..], opts.lenient), len)))
+}
+
+/// Read a topic filter. Lenient decoding tolerates invalid strings, like it does for packets.
+fn read_topic_filter<'a>(
+    buf: &'a [u8],
+    offset: &mut usize,
+    lenient: bool,
+) -> Result<&'a str, Error> {
+    let mut warnings = Warnings::new();
+    let mut ctx = DecodeCtx::new(&DecodeOptions::new().lenient(lenient), &mut warnings);
+    read_str(buf, offset, Field::TopicFilter, &mut ctx)
+}
+
+/// Lazy iterator over the items of a [`SubscribeRef`], [`UnsubscribeRef`] or [`SubackRef`].
+///
+/// Each item is validated as it is read. After the first error, the iterator ends.
+///
+/// [`SubscribeRef`]: struct.SubscribeRef.html
+/// [`UnsubscribeRef`]: struct.UnsubscribeRef.html
+/// [`SubackRef`]: struct.SubackRef.html
+#[derive(Clone)]
+pub struct PayloadIter<'a, T> {
+    buf: &'a [u8],
+    offset: usize,
+    lenient: bool,
+    read: fn(&'a [u8], &mut usize, bool) -> Result<T, Error>,
+}
+
+impl<'a, T> Iterator for PayloadIter<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+        let res = (self.read)(self.buf, &mut self.offset, self.lenient);
+        if res.is_err() {
+            self.offset = self.buf.len();
+        }
+        Some(res)
+    }
+}
+
+impl<'a, T> core::iter::FusedIterator for PayloadIter<'a, T> {}
+
+impl<'a, T> core::fmt::Debug for PayloadIter<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("PayloadIter")
+            .field("remaining", &&self.buf[self.offset..])
+            .finish()
+    }
+}
+
+/// Borrowed version of [`SubscribeTopic`], returned by [`SubscribeRef::topics()`].
+///
+/// [`SubscribeTopic`]: struct.SubscribeTopic.html
+/// [`SubscribeRef::topics()`]: struct.SubscribeRef.html#method.topics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubscribeTopicRef<'a> {
+    pub topic_path: &'a str,
+    pub qos: QoS,
+}
+
+/// [`Subscribe`] packet that borrows its topics from the decoding buffer.
+///
+/// Unlike `Subscribe`, it doesn't allocate or copy, and isn't limited to 5 topics without the
+/// `std` feature. Topics are only checked when iterating over them. See the [crate docs] to
+/// decode it alongside other packets.
+///
+/// ```
+/// # use mqttrs::*;
+/// # use core::convert::TryFrom;
+/// let buf = [0b10000010, 11, 0, 10, 0, 1, b'a', 1, 0, 2, b'b', b'/', 2];
+/// let opts = DecodeOptions::new();
+/// let (subscribe, len) = SubscribeRef::from_slice(&buf, &opts).unwrap().unwrap();
+/// assert_eq!(len, buf.len());
+/// assert_eq!(subscribe.pid, Pid::try_from(10).unwrap());
+/// let mut topics = subscribe.topics();
+/// assert_eq!(Some(Ok(SubscribeTopicRef { topic_path: "a", qos: QoS::AtLeastOnce })), topics.next());
+/// assert_eq!(Some(Ok(SubscribeTopicRef { topic_path: "b/", qos: QoS::ExactlyOnce })), topics.next());
+/// assert_eq!(None, topics.next());
+/// ```
+///
+/// [`Subscribe`]: struct.Subscribe.html
+/// [crate docs]: index.html#large-payloads-without-std
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubscribeRef<'a> {
+    pub pid: Pid,
+    topics: &'a [u8],
+    lenient: bool,
+}
+
+impl<'a> SubscribeRef<'a> {
+    /// Decode a `Subscribe` packet from the start of `buf`, also returning its length.
+    ///
+    /// Returns `Ok(None)` if `buf` doesn't contain a complete packet yet, and
+    /// `Error::InvalidHeader` if it's not a `Subscribe` packet. The size limits and leniency of
+    /// `opts` apply like they do for [`decode_slice_with_options()`], but no warnings are
+    /// reported.
+    ///
+    /// [`decode_slice_with_options()`]: fn.decode_slice_with_options.html
+    pub fn from_slice<const N: usize, const L: usize>(
+        buf: &'a [u8],
+        opts: &DecodeOptions<N, L>,
+    ) -> Result<Option<(Self, usize)>, Error> {
+        read_pid_frame(buf, opts, PacketType::Subscribe, |pid, topics, lenient| {
+            SubscribeRef {
+                pid,
+                topics,
+                lenient,
+            }
+        })
+    }
+
+    pub fn topics(&self) -> PayloadIter<'a, SubscribeTopicRef<'a>> {
+        PayloadIter {
+            buf: self.topics,
+            offset: 0,
+            lenient: self.lenient,
+            read: |buf, offset, lenient| {
+                let topic_path = read_topic_filter(buf, offset, lenient)?;
+                // Values above 2, including those with reserved bits set, are rejected ([MQTT-3.8.3-4]).
+                let qos = QoS::from_u8(read_u8(buf, offset)?)?;
+                Ok(SubscribeTopicRef { topic_path, qos })
+            },
+        }
+    }
+}
+
+/// [`Unsubscribe`] packet that borrows its topics from the decoding buffer.
+///
+/// See [`SubscribeRef`].
+///
+/// [`Unsubscribe`]: struct.Unsubscribe.html
+/// [`SubscribeRef`]: struct.SubscribeRef.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnsubscribeRef<'a> {
+    pub pid: Pid,
+    topics: &'a [u8],
+    lenient: bool,
+}
+
+impl<'a> UnsubscribeRef<'a> {
+    /// Decode an `Unsubscribe` packet from the start of `buf`, also returning its length.
+    ///
+    /// Returns `Ok(None)` if `buf` doesn't contain a complete packet yet, and
+    /// `Error::InvalidHeader` if it's not an `Unsubscribe` packet. `opts` applies like for
+    /// [`SubscribeRef::from_slice()`].
+    ///
+    /// [`SubscribeRef::from_slice()`]: struct.SubscribeRef.html#method.from_slice
+    pub fn from_slice<const N: usize, const L: usize>(
+        buf: &'a [u8],
+        opts: &DecodeOptions<N, L>,
+    ) -> Result<Option<(Self, usize)>, Error> {
+        read_pid_frame(
+            buf,
+            opts,
+            PacketType::Unsubscribe,
+            |pid, topics, lenient| UnsubscribeRef {
+                pid,
+                topics,
+                lenient,
+            },
+        )
+    }
+
+    pub fn topics(&self) -> PayloadIter<'a, &'a str> {
+        PayloadIter {
+            buf: self.topics,
+            offset: 0,
+            lenient: self.lenient,
+            read: read_topic_filter,
+        }
+    }
+}
+
+/// [`Suback`] packet that borrows its return codes from the decoding buffer.
+///
+/// See [`SubscribeRef`].
+///
+/// [`Suback`]: struct.Suback.html
+/// [`SubscribeRef`]: struct.SubscribeRef.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubackRef<'a> {
+    pub pid: Pid,
+    return_codes: &'a [u8],
+}
+
+impl<'a> SubackRef<'a> {
+    /// Decode a `Suback` packet from the start of `buf`, also returning its length.
+    ///
+    /// Returns `Ok(None)` if `buf` doesn't contain a complete packet yet, and
+    /// `Error::InvalidHeader` if it's not a `Suback` packet. `opts` applies like for
+    /// [`SubscribeRef::from_slice()`].
+    ///
+    /// [`SubscribeRef::from_slice()`]: struct.SubscribeRef.html#method.from_slice
+    pub fn from_slice<const N: usize, const L: usize>(
+        buf: &'a [u8],
+        opts: &DecodeOptions<N, L>,
+    ) -> Result<Option<(Self, usize)>, Error> {
+        read_pid_frame(buf, opts, PacketType::Suback, |pid, return_codes, _| {
+            SubackRef { pid, return_codes }
+        })
+    }
+
+    pub fn return_codes(&self) -> PayloadIter<'a, SubscribeReturnCodes> {
+        PayloadIter {
+            buf: self.return_codes,
+            offset: 0,
+            lenient: false,
+            read: |buf, offset, _| SubscribeReturnCodes::from_buffer(buf, offset),
+        }
+    }
 }
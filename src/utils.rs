@@ -1,4 +1,4 @@
-use crate::encoder::write_u16;
+use crate::{decoder::read_u16, encoder::write_u16};
 use core::{convert::TryFrom, fmt, num::NonZeroU16};
 
 #[cfg(feature = "derive")]
@@ -28,6 +28,8 @@ pub enum Error {
     /// Tried to decode a ConnectReturnCode > 5.
     InvalidConnectReturnCode(u8),
     /// Tried to decode an unknown protocol.
+    ///
+    /// Without the `std` feature, the name is truncated to its first 10 bytes (on a char boundary).
     #[cfg(feature = "std")]
     InvalidProtocol(std::string::String, u8),
     #[cfg(not(feature = "std"))]
@@ -39,14 +41,77 @@ pub enum Error {
     /// The difference with `WriteZero`/`UnexpectedEof` is that it refers to an invalid/corrupt
     /// length rather than a buffer size issue.
     InvalidLength,
-    /// Trying to decode a non-utf8 string.
-    InvalidString(core::str::Utf8Error),
+    /// Tried to decode a packet bigger than the configured maximum size, or to encode a packet with
+    /// a remaining length bigger than the MQTT limit of 268435455 bytes.
+    PacketTooLarge,
+    /// Tried to encode a string or binary field longer than 65535 bytes.
+    FieldTooLong(Field),
+    /// Tried to decode a packet whose fields extend past its remaining_length, including a string
+    /// or binary field whose length prefix is too big.
+    TruncatedBody,
+    /// Tried to decode a packet whose fields end before its remaining_length.
+    TrailingBytes,
+    /// Tried to decode a [`Connect`] with the reserved flag set ([MQTT-3.1.2-3]).
+    ///
+    /// [`Connect`]: struct.Connect.html
+    /// [MQTT-3.1.2-3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+    ReservedConnectFlag,
+    /// Tried to decode a [`Connect`] with will QoS or will retain flags but no will flag
+    /// ([MQTT-3.1.2-11], [MQTT-3.1.2-13]).
+    ///
+    /// [`Connect`]: struct.Connect.html
+    /// [MQTT-3.1.2-11]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+    /// [MQTT-3.1.2-13]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+    InvalidWillFlags,
+    /// Tried to encode or decode a [`Connect`] with a password but no username ([MQTT-3.1.2-22]).
+    ///
+    /// [`Connect`]: struct.Connect.html
+    /// [MQTT-3.1.2-22]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+    PasswordWithoutUsername,
+    /// Tried to encode or decode a [`Subscribe`], [`Unsubscribe`] or [`Suback`] without any topic
+    /// or return code ([MQTT-3.8.3-3], [MQTT-3.10.3-2]).
+    ///
+    /// [`Subscribe`]: struct.Subscribe.html
+    /// [`Unsubscribe`]: struct.Unsubscribe.html
+    /// [`Suback`]: struct.Suback.html
+    /// [MQTT-3.8.3-3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718063
+    /// [MQTT-3.10.3-2]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718072
+    EmptyPayload,
+    /// Tried to decode a non-utf8 string, in the given field.
+    InvalidString(Field, core::str::Utf8Error),
+    /// Tried to encode or decode a string containing U+0000, which is forbidden by
+    /// [MQTT-1.5.3-2], or one of the code points that [MQTT-1.5.3] says shouldn't be sent: the
+    /// control characters U+0001 to U+001F and U+007F to U+009F, and the Unicode non-characters.
+    ///
+    /// [MQTT-1.5.3-2]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718017
+    /// [MQTT-1.5.3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718017
+    DisallowedCodePoint(Field),
+    /// Tried to build a packet with an empty topic, a topic name containing wildcards, or a topic
+    /// filter with misplaced wildcards ([MQTT-4.7.1-1], [MQTT-4.7.1-2], [MQTT-4.7.1-3],
+    /// [MQTT-4.7.3-1]).
+    ///
+    /// [MQTT-4.7.1-1]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718107
+    /// [MQTT-4.7.1-2]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718107
+    /// [MQTT-4.7.1-3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718107
+    /// [MQTT-4.7.3-1]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718109
+    InvalidTopic(Field),
+    /// Tried to build a [`Connect`] with an empty client id without requesting a clean session
+    /// ([MQTT-3.1.3-7]).
+    ///
+    /// [`Connect`]: struct.Connect.html
+    /// [MQTT-3.1.3-7]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718028
+    InvalidClientId,
     /// Catch-all error when converting from `std::io::Error`.
     ///
     /// Note: Only available when std is available.
     /// You'll hopefully never see this.
     #[cfg(feature = "std")]
     IoError(ErrorKind, std::string::String),
+    /// Error returned by an `embedded_io::Write`.
+    ///
+    /// Note: Only available with the `embedded-io` feature.
+    #[cfg(feature = "embedded-io")]
+    EmbeddedIoError(embedded_io::ErrorKind),
 }
 
 #[cfg(feature = "std")]
@@ -78,6 +143,106 @@ impl From<IoError> for Error {
     }
 }
 
+/// String or binary field of a packet, used to report which one is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// [`Connect`] protocol name.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    ProtocolName,
+    /// [`Connect`] client identifier.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    ClientId,
+    /// [`LastWill`] topic.
+    ///
+    /// [`LastWill`]: struct.LastWill.html
+    WillTopic,
+    /// [`LastWill`] message.
+    ///
+    /// [`LastWill`]: struct.LastWill.html
+    WillMessage,
+    /// [`Connect`] user name.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    Username,
+    /// [`Connect`] password.
+    ///
+    /// [`Connect`]: struct.Connect.html
+    Password,
+    /// [`Publish`] topic name.
+    ///
+    /// [`Publish`]: struct.Publish.html
+    TopicName,
+    /// [`Subscribe`] or [`Unsubscribe`] topic filter.
+    ///
+    /// [`Subscribe`]: struct.Subscribe.html
+    /// [`Unsubscribe`]: struct.Unsubscribe.html
+    TopicFilter,
+}
+
+/// Check the rules of [MQTT-1.5.3] that `str` doesn't already guarantee.
+///
+/// [MQTT-1.5.3]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718017
+pub(crate) fn check_str(s: &str, field: Field) -> Result<(), Error> {
+    check_bytes(s.as_bytes(), field)?;
+    // Printable ASCII, by far the most common case, is always allowed.
+    if s.bytes().all(|b| (0x20..0x7F).contains(&b)) || !s.chars().any(disallowed_char) {
+        Ok(())
+    } else {
+        Err(Error::DisallowedCodePoint(field))
+    }
+}
+
+/// U+0000, control characters and non-characters.
+fn disallowed_char(c: char) -> bool {
+    let c = c as u32;
+    c <= 0x1F
+        || (0x7F..=0x9F).contains(&c)
+        || (0xFDD0..=0xFDEF).contains(&c)
+        || c & 0xFFFE == 0xFFFE
+}
+
+/// Check that `s` is a valid topic name: not empty and without wildcards.
+pub(crate) fn check_topic_name(s: &str, field: Field) -> Result<(), Error> {
+    check_str(s, field)?;
+    if s.is_empty() || s.contains(&['+', '#'][..]) {
+        Err(Error::InvalidTopic(field))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that `s` is a valid topic filter: not empty, with wildcards only as whole levels, and `#`
+/// only as the last level.
+pub(crate) fn check_topic_filter(s: &str) -> Result<(), Error> {
+    check_str(s, Field::TopicFilter)?;
+    if s.is_empty() {
+        return Err(Error::InvalidTopic(Field::TopicFilter));
+    }
+    let mut levels = s.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "+" => true,
+            "#" => levels.peek().is_none(),
+            _ => !level.contains(&['+', '#'][..]),
+        };
+        if !valid {
+            return Err(Error::InvalidTopic(Field::TopicFilter));
+        }
+    }
+    Ok(())
+}
+
+/// Check that a string or binary field fits its 2-bytes length prefix.
+pub(crate) fn check_bytes(bytes: &[u8], field: Field) -> Result<(), Error> {
+    if bytes.len() > u16::MAX as usize {
+        Err(Error::FieldTooLong(field))
+    } else {
+        Ok(())
+    }
+}
+
 /// Packet Identifier.
 ///
 /// For packets with [`QoS::AtLeastOne` or `QoS::ExactlyOnce`] delivery.
@@ -115,15 +280,18 @@ impl Pid {
         Pid(NonZeroU16::new(1).unwrap())
     }
 
+    /// Wrap a `NonZeroU16` as a `Pid`. Unlike `try_from()`, this can be used in `const` contexts.
+    pub const fn from_nonzero(n: NonZeroU16) -> Self {
+        Pid(n)
+    }
+
     /// Get the `Pid` as a raw `u16`.
-    pub fn get(self) -> u16 {
+    pub const fn get(self) -> u16 {
         self.0.get()
     }
 
-    pub(crate) fn from_buffer<'a>(buf: &'a [u8], offset: &mut usize) -> Result<Self, Error> {
-        let pid = ((buf[*offset] as u16) << 8) | buf[*offset + 1] as u16;
-        *offset += 2;
-        Self::try_from(pid)
+    pub(crate) fn from_buffer(buf: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        Self::try_from(read_u16(buf, offset)?)
     }
 
     pub(crate) fn to_buffer(self, buf: &mut [u8], offset: &mut usize) -> Result<(), Error> {
@@ -156,7 +324,7 @@ impl core::ops::Sub<u16> for Pid {
     /// Adding a `u16` to a `Pid` will wrap around and avoid 0.
     fn sub(self, u: u16) -> Pid {
         let n = match self.get().overflowing_sub(u) {
-            (0, _) => core::u16::MAX,
+            (0, _) => u16::MAX,
             (n, false) => n,
             (n, true) => n - 1,
         };
@@ -198,8 +366,8 @@ pub enum QoS {
 }
 
 impl QoS {
-    pub(crate) fn to_u8(&self) -> u8 {
-        match *self {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
             QoS::AtMostOnce => 0,
             QoS::AtLeastOnce => 1,
             QoS::ExactlyOnce => 2,
@@ -276,15 +444,15 @@ mod test {
         let t: Vec<(u16, u16, u16, u16)> = vec![
             (2, 1, 1, 3),
             (100, 1, 99, 101),
-            (1, 1, core::u16::MAX, 2),
-            (1, 2, core::u16::MAX - 1, 3),
-            (1, 3, core::u16::MAX - 2, 4),
-            (core::u16::MAX, 1, core::u16::MAX - 1, 1),
-            (core::u16::MAX, 2, core::u16::MAX - 2, 2),
-            (10, core::u16::MAX, 10, 10),
+            (1, 1, u16::MAX, 2),
+            (1, 2, u16::MAX - 1, 3),
+            (1, 3, u16::MAX - 2, 4),
+            (u16::MAX, 1, u16::MAX - 1, 1),
+            (u16::MAX, 2, u16::MAX - 2, 2),
+            (10, u16::MAX, 10, 10),
             (10, 0, 10, 10),
             (1, 0, 1, 1),
-            (core::u16::MAX, 0, core::u16::MAX, core::u16::MAX),
+            (u16::MAX, 0, u16::MAX, u16::MAX),
         ];
         for (cur, d, prev, next) in t {
             let sub = Pid::try_from(cur).unwrap() - d;